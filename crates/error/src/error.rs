@@ -19,6 +19,10 @@ pub enum Error {
     OutOfBounds,
     /// Buffer pool at capacity
     BufferPoolFull,
+    /// A page read from disk failed its checksum verification.
+    Corruption { page_id: usize },
+    /// A page could not be deleted because it is pinned.
+    PagePinned { page_id: usize },
 }
 
 impl std::error::Error for Error {}
@@ -31,6 +35,8 @@ impl std::fmt::Display for Error {
             Error::ArithmeticOverflow => write!(f, "Arithmetic overflow"),
             Error::OutOfBounds => write!(f, "Out of bounds"),
             Error::BufferPoolFull => write!(f, "Buffer pool is at capacity"),
+            Error::Corruption { page_id } => write!(f, "Page {} is corrupted", page_id),
+            Error::PagePinned { page_id } => write!(f, "Page {} is pinned", page_id),
         }
     }
 }
@@ -0,0 +1,131 @@
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::buffer_pool::BufferPoolManager;
+
+/// Writes dirty pages that are not pinned back to disk on a background thread, so that eviction
+/// usually finds clean frames and fetches rarely wait on a write.
+///
+/// Every `interval`, the writer writes up to `max_pages_per_round` pages, which bounds the I/O
+/// it adds to the store. The thread only holds a weak reference to the pool between rounds, so
+/// it does not keep the pool alive. It stops when the pool is dropped or when the writer is
+/// dropped, which waits for the current round to finish.
+pub(crate) struct BackgroundWriter {
+    /// Dropped to stop the thread.
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BackgroundWriter {
+    pub(crate) fn start(
+        bpm: &Arc<BufferPoolManager>,
+        interval: Duration,
+        max_pages_per_round: usize,
+    ) -> Self {
+        assert!(
+            max_pages_per_round > 0,
+            "Background writer must write at least one page per round"
+        );
+        let (stop, stopped) = mpsc::channel::<()>();
+        let bpm = Arc::downgrade(bpm);
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                if !Self::run_round(&bpm, max_pages_per_round) {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns whether the pool is still alive.
+    fn run_round(bpm: &Weak<BufferPoolManager>, max_pages: usize) -> bool {
+        let Some(bpm) = bpm.upgrade() else {
+            return false;
+        };
+        // Pages that fail to be written stay dirty and are retried in a later round, or by
+        // the eviction that needs their frame, which reports the error.
+        let _ = bpm.write_back_unpinned_pages(max_pages);
+        true
+    }
+}
+
+impl Drop for BackgroundWriter {
+    /// Stops the thread and waits for it to finish its current round.
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::fault_injecting_page_store::FaultInjectingPageStore;
+    use crate::disk::memory_page_store::MemoryPageStore;
+    use crate::disk::page_store::PageStore;
+    use crate::replacer::lru_replacer::LruReplacer;
+    use crate::Result;
+    use std::time::Instant;
+
+    #[test]
+    fn test_writes_dirty_pages_in_background() -> Result<()> {
+        let store = Arc::new(FaultInjectingPageStore::new(Arc::new(
+            MemoryPageStore::new(),
+        )));
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(4, store.clone(), replacer));
+
+        let mut page_ids = vec![];
+        for i in 0..4 {
+            let mut handle = BufferPoolManager::create_page_handle(&bpm)?;
+            handle.page_frame_mut().write(0, &[i + 1]);
+            page_ids.push(handle.page_frame().page_id());
+        }
+        // a pinned page is left alone
+        let mut pinned = BufferPoolManager::fetch_page_mut_handle(&bpm, &page_ids[3])?;
+        pinned.page_frame_mut().write(0, &[42]);
+
+        let _writer = BackgroundWriter::start(&bpm, Duration::from_millis(1), 1);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let written = |store: &FaultInjectingPageStore| -> Result<usize> {
+            store.sync()?;
+            let mut written = 0;
+            for page_id in &page_ids[..3] {
+                written += (store.inner().read(page_id)?.unwrap()[0] != 0) as usize;
+            }
+            Ok(written)
+        };
+        while written(&store)? < 3 {
+            assert!(Instant::now() < deadline, "Pages were not written back");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(0, store.inner().read(&page_ids[3])?.unwrap()[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stops_when_pool_is_dropped() {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(4, disk, replacer));
+
+        let writer = BackgroundWriter::start(&bpm, Duration::from_millis(1), 1);
+        drop(bpm);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !writer.thread.as_ref().unwrap().is_finished() {
+            assert!(Instant::now() < deadline, "Writer did not stop");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
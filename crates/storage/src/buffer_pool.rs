@@ -1,236 +1,1085 @@
+use bytes::Bytes;
+use parking_lot::{Condvar, Mutex, MutexGuard, RwLock};
 use rustdb_error::Error;
 
-use crate::disk::disk_manager::DiskManager;
-use crate::frame::PageFrame;
+use crate::buffer_pool_stats::{BufferPoolStats, FrameInfo, StatsCounters};
+use crate::disk::disk_scheduler::DiskScheduler;
+use crate::disk::page_store::PageStore;
+use crate::frame::{FrameHeader, PageFrameReadGuard, PageFrameWriteGuard};
 use crate::frame_handle::{PageFrameMutHandle, PageFrameRefHandle};
+use crate::page::{page_data_size, INVALID_PAGE_ID};
 use crate::typedef::{FrameId, PageId};
-use std::collections::{HashMap, VecDeque};
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::Result;
 
-use crate::replacer::replacer::Replacer;
+use crate::replacer::replacer::{AccessType, Replacer};
 
+/// The buffer pool is shared between threads as an `Arc<BufferPoolManager>`. Its state is
+/// protected by separate latches:
+///
+/// - `state` protects the page table, the free list and the scan ring. It is held while a
+///   frame is looked up, installed or evicted.
+/// - `replacer` protects the replacer. Pin counts are only changed while it is held, so that
+///   a frame is evictable in the replacer exactly when its pin count is 0.
+/// - each frame has a reader/writer latch protecting its page, which the page handles hold.
+/// - `frames` protects the list of frames. It is only held to look up a frame, and to add or
+///   retire frames while holding `state` when the pool is resized.
+///
+/// `state` is always acquired before `replacer`. A frame latch is only acquired while holding
+/// `state` if the frame is not pinned, and the latch of an unpinned frame is never held by
+/// anyone else, since frames are pinned before they are latched and unlatched before they are
+/// unpinned. The one exception is an eviction, which holds the latch of its victim while it
+/// writes the page back without holding `state`. The victim is neither in the page table, the
+/// free list nor the replacer meanwhile, so nobody else looks for its latch.
+///
+/// `resizing` is held for the whole of a resize, before `state`, so that a resize does not
+/// count the frames another resize is still evicting.
 pub(crate) struct BufferPoolManager {
-    frames: Vec<PageFrame>,
+    /// Indexed by frame id. The slot of a frame retired by [`BufferPoolManager::resize`] stays
+    /// `None` until the pool grows again, unless it is at the end.
+    frames: RwLock<Vec<Option<Arc<FrameHeader>>>>,
+    state: Mutex<PoolState>,
+    replacer: Mutex<Box<dyn Replacer>>,
+    resizing: Mutex<()>,
+    disk_scheduler: DiskScheduler,
+    /// The page size of the page store, which every frame holds.
+    page_size: usize,
+    scan_ring_size: usize,
+    /// The frame [`BufferPoolManager::write_back_unpinned_pages`] continues at.
+    writer_hand: AtomicUsize,
+    stats: StatsCounters,
+    /// How long to wait for a frame when every frame is pinned. See
+    /// [`BufferPoolManager::with_wait_timeout`].
+    wait_timeout: Option<Duration>,
+    /// Signalled, while holding `state`, when a frame becomes free or evictable and
+    /// `frame_waiters` is not 0, and when an eviction finished writing back a page.
+    frame_released: Condvar,
+    frame_waiters: AtomicUsize,
+}
+
+struct PoolState {
     page_table: HashMap<PageId, FrameId>,
-    replacer: Box<dyn Replacer>,
     free_list: VecDeque<FrameId>,
-    disk_manager: Arc<RwLock<DiskManager>>,
+    /// Frames holding pages brought in by scans, oldest first, along with the page each frame
+    /// held when it was added. See [`BufferPoolManager::with_scan_ring_size`].
+    scan_ring: VecDeque<(FrameId, PageId)>,
+    /// Pages that an eviction is writing back without holding `state`. They are not in the
+    /// page table meanwhile, and fetching or deleting them waits until the write completes, so
+    /// that an older copy is never read from disk.
+    writing_back: HashSet<PageId>,
 }
 
 impl BufferPoolManager {
     pub(crate) fn new(
         pool_size: usize,
-        disk_manager: Arc<RwLock<DiskManager>>,
-        replacer: Box<dyn Replacer>,
+        page_store: Arc<dyn PageStore>,
+        mut replacer: Box<dyn Replacer>,
     ) -> Self {
-        let mut pages = Vec::with_capacity(pool_size);
-        pages.resize_with(pool_size, PageFrame::new);
+        // The replacer may have been created for another number of frames.
+        replacer.set_capacity(pool_size);
+        let page_size = page_store.page_size();
+        let frames = (0..pool_size)
+            .map(|_| Some(Arc::new(FrameHeader::new(page_size))))
+            .collect();
 
         Self {
-            frames: pages,
-            page_table: HashMap::new(),
-            replacer,
-            free_list: (0..pool_size).collect(),
-            disk_manager,
+            frames: RwLock::new(frames),
+            state: Mutex::new(PoolState {
+                page_table: HashMap::new(),
+                free_list: (0..pool_size).collect(),
+                scan_ring: VecDeque::new(),
+                writing_back: HashSet::new(),
+            }),
+            replacer: Mutex::new(replacer),
+            resizing: Mutex::new(()),
+            disk_scheduler: DiskScheduler::new(page_store),
+            page_size,
+            scan_ring_size: 0,
+            writer_hand: AtomicUsize::new(0),
+            stats: StatsCounters::default(),
+            wait_timeout: None,
+            frame_released: Condvar::new(),
+            frame_waiters: AtomicUsize::new(0),
+        }
+    }
+
+    /// Limits the frames that pages fetched with [`AccessType::Scan`] are brought into to a
+    /// ring of `ring_size` frames, like PostgreSQL's bulk read strategy. Once the ring is full,
+    /// each scan miss reuses the frame of the oldest scanned page, so a large scan only ever
+    /// displaces `ring_size` other pages. A scanned page that is accessed by anything but a scan
+    /// leaves the ring. A ring size of 0, the default, disables the ring.
+    pub(crate) fn with_scan_ring_size(mut self, ring_size: usize) -> Self {
+        assert!(
+            ring_size <= self.capacity(),
+            "Scan ring must not be larger than the buffer pool"
+        );
+        self.scan_ring_size = ring_size;
+        self
+    }
+
+    /// Makes fetches and page creations wait up to `timeout` for a frame to be unpinned when
+    /// every frame is pinned, instead of failing with [`Error::BufferPoolFull`] right away.
+    pub(crate) fn with_wait_timeout(mut self, timeout: Duration) -> Self {
+        self.wait_timeout = Some(timeout);
+        self
+    }
+
+    /// Returns a frame that is in use, i.e. one that is not retired.
+    fn frame(&self, frame_id: FrameId) -> Arc<FrameHeader> {
+        self.frames.read()[frame_id]
+            .clone()
+            .expect("Frame is retired")
+    }
+
+    /// Grows or shrinks the pool to `new_size` frames.
+    ///
+    /// Growing adds free frames. Shrinking retires free frames first, then evicts unpinned pages,
+    /// writing them back if they are dirty. If more than `new_size` frames are pinned, the pool
+    /// is left unchanged and [`Error::BufferPoolFull`] is returned. If writing back a page fails,
+    /// or other threads pin the remaining pages while a page is written back, the error is
+    /// returned and the pool keeps its size, with the frames freed so far on the free list.
+    pub(crate) fn resize(&self, new_size: usize) -> Result<()> {
+        let _resizing = self.resizing.lock();
+        let mut state = self.state.lock();
+        let capacity = self.capacity();
+        if new_size >= capacity {
+            self.add_frames(&mut state, new_size - capacity);
+            return Ok(());
+        }
+
+        let frames = self.frames.read().clone();
+        let pinned_count = frames
+            .iter()
+            .flatten()
+            .filter(|frame| frame.pin_count() > 0)
+            .count();
+        if pinned_count > new_size {
+            return Err(Error::BufferPoolFull);
+        }
+
+        // retire the frames with the highest ids, so that the slots at the end can be dropped
+        let retire_count = capacity - new_size;
+        let mut free_frame_ids: Vec<FrameId> = state.free_list.iter().copied().collect();
+        free_frame_ids.sort_unstable_by(|a, b| b.cmp(a));
+        free_frame_ids.truncate(retire_count);
+        state
+            .free_list
+            .retain(|frame_id| !free_frame_ids.contains(frame_id));
+        let mut retired = free_frame_ids;
+
+        // `state` is released while a dirty page is written back, and other threads may pin
+        // frames meanwhile, so the unpinned frame with the highest id is looked up each time
+        let mut result = Ok(());
+        while retired.len() < retire_count {
+            let Some(frame_id) = state
+                .page_table
+                .values()
+                .copied()
+                .filter(|&frame_id| self.frame(frame_id).pin_count() == 0)
+                .max()
+            else {
+                result = Err(Error::BufferPoolFull);
+                break;
+            };
+            self.replacer.lock().remove(frame_id);
+            if let Err(e) = self.write_back_and_reset(&mut state, frame_id) {
+                result = Err(e);
+                break;
+            }
+            retired.push(frame_id);
+        }
+
+        if result.is_err() {
+            for frame_id in retired {
+                self.release_free_frame(&mut state, frame_id);
+            }
+            return result;
+        }
+        self.retire_frames(&mut state, &retired);
+        Ok(())
+    }
+
+    /// Adds `count` free frames, reusing retired slots first. The caller holds `state`.
+    fn add_frames(&self, state: &mut PoolState, count: usize) {
+        let mut added = Vec::with_capacity(count);
+        let slot_count = {
+            let mut frames = self.frames.write();
+            for (frame_id, slot) in frames.iter_mut().enumerate() {
+                if added.len() == count {
+                    break;
+                }
+                if slot.is_none() {
+                    *slot = Some(Arc::new(FrameHeader::new(self.page_size)));
+                    added.push(frame_id);
+                }
+            }
+            while added.len() < count {
+                added.push(frames.len());
+                frames.push(Some(Arc::new(FrameHeader::new(self.page_size))));
+            }
+            frames.len()
+        };
+
+        self.replacer.lock().set_capacity(slot_count);
+        for frame_id in added {
+            self.release_free_frame(state, frame_id);
         }
     }
 
+    /// Retires frames that are neither free nor hold a page. The caller holds `state`.
+    fn retire_frames(&self, state: &mut PoolState, frame_ids: &[FrameId]) {
+        state
+            .scan_ring
+            .retain(|(frame_id, _)| !frame_ids.contains(frame_id));
+        let slot_count = {
+            let mut frames = self.frames.write();
+            for &frame_id in frame_ids {
+                frames[frame_id] = None;
+            }
+            while frames.last().is_some_and(Option::is_none) {
+                frames.pop();
+            }
+            frames.len()
+        };
+        self.replacer.lock().set_capacity(slot_count);
+    }
+
+    /// Finds a frame like [`BufferPoolManager::try_get_free_frame`]. If every frame is pinned,
+    /// waits for a frame to be released until the wait timeout passes. `state` is released
+    /// while waiting.
+    fn get_free_frame(
+        &self,
+        state: &mut MutexGuard<'_, PoolState>,
+        access_type: AccessType,
+    ) -> Result<FrameId> {
+        let Some(timeout) = self.wait_timeout else {
+            return self.try_get_free_frame(state, access_type);
+        };
+        let deadline = Instant::now() + timeout;
+
+        // register as a waiter before looking for a frame, so that a frame released after the
+        // lookup signals us
+        self.frame_waiters.fetch_add(1, Ordering::SeqCst);
+        let mut waited = false;
+        let result = loop {
+            match self.try_get_free_frame(state, access_type) {
+                Err(Error::BufferPoolFull) if Instant::now() < deadline => {
+                    waited = true;
+                    self.frame_released.wait_until(state, deadline);
+                }
+                result => break result,
+            }
+        };
+        self.frame_waiters.fetch_sub(1, Ordering::SeqCst);
+
+        if waited {
+            StatsCounters::increment(&self.stats.pin_waits);
+        }
+        result
+    }
+
+    /// Wakes up the threads waiting for a frame, if any.
+    fn notify_frame_waiters(&self) {
+        if self.frame_waiters.load(Ordering::SeqCst) > 0 {
+            // taking the latch makes sure that each waiter either sees the released frame or is
+            // already waiting
+            let _state = self.state.lock();
+            self.frame_released.notify_all();
+        }
+    }
+
+    /// Puts a frame on the free list. The caller holds `state`.
+    fn release_free_frame(&self, state: &mut PoolState, frame_id: FrameId) {
+        state.free_list.push_back(frame_id);
+        self.frame_released.notify_all();
+    }
+
     /// try to find a frame in the buffer pool that is free, or pin count of zero
-    fn get_free_frame(&mut self) -> Result<FrameId> {
+    fn try_get_free_frame(
+        &self,
+        state: &mut MutexGuard<'_, PoolState>,
+        access_type: AccessType,
+    ) -> Result<FrameId> {
+        // scans reuse the oldest frame of a full scan ring
+        if access_type == AccessType::Scan {
+            if let Some(frame_id) = self.reuse_scan_ring_frame(state)? {
+                return Ok(frame_id);
+            }
+        }
+
         // use the freelist if it has available frame
-        if let Some(frame_id) = self.free_list.pop_front() {
+        if let Some(frame_id) = state.free_list.pop_front() {
             return Ok(frame_id);
         }
 
         // otherwise evict a frame
-        let frame_id = self.replacer.evict().ok_or(Error::BufferPoolFull)?;
-        let frame = &mut self.frames[frame_id];
+        let frame_id = self.replacer.lock().evict().ok_or(Error::BufferPoolFull)?;
+        self.write_back_and_reset(state, frame_id)?;
+
+        Ok(frame_id)
+    }
+
+    /// Takes the oldest frame out of a full scan ring, if it still holds the scanned page and
+    /// is not pinned. Otherwise the frame is dropped from the ring and `None` is returned.
+    fn reuse_scan_ring_frame(
+        &self,
+        state: &mut MutexGuard<'_, PoolState>,
+    ) -> Result<Option<FrameId>> {
+        if self.scan_ring_size == 0 || state.scan_ring.len() < self.scan_ring_size {
+            return Ok(None);
+        }
+
+        let (frame_id, page_id) = state.scan_ring.pop_front().unwrap();
+        if state.page_table.get(&page_id) != Some(&frame_id) || self.frame(frame_id).pin_count() > 0
+        {
+            return Ok(None);
+        }
+
+        self.replacer.lock().remove(frame_id);
+        if let Err(e) = self.write_back_and_reset(state, frame_id) {
+            state.scan_ring.push_front((frame_id, page_id));
+            return Err(e);
+        }
+
+        Ok(Some(frame_id))
+    }
+
+    /// Writes a frame that was taken out of the replacer back to disk if it is dirty, and
+    /// resets it. If the write fails, the frame keeps its page and is handed back to the
+    /// replacer.
+    ///
+    /// `state` is released while the page is written, so that the write does not hold up
+    /// fetches of other pages. The page is taken out of the page table meanwhile, and fetches
+    /// of it wait until the write completes.
+    fn write_back_and_reset(
+        &self,
+        state: &mut MutexGuard<'_, PoolState>,
+        frame_id: FrameId,
+    ) -> Result<()> {
+        let frame = self.frame(frame_id);
         assert!(
             frame.pin_count() == 0,
             "If page is evicted from replacer, it's pin count must be 0."
         );
+        let mut page_frame = frame.write();
+        let page_id = page_frame.page_id();
+
+        // if a frame is evicted to make space, remove the stale record in the page table
+        state.page_table.remove(&page_id);
 
         // flush the evicted page to disk if it is dirty
-        if frame.is_dirty() {
-            let mut disk = self.disk_manager.write().unwrap();
-            disk.write(&frame.page_id(), frame.data()).unwrap();
+        if frame.take_dirty() {
+            state.writing_back.insert(page_id);
+            let result =
+                MutexGuard::unlocked(state, || self.write_page(page_id, page_frame.data()));
+            state.writing_back.remove(&page_id);
+            self.frame_released.notify_all();
+
+            if let Err(e) = result {
+                // the page stays cached and dirty, so put it back up for eviction
+                frame.set_dirty(true);
+                state.page_table.insert(page_id, frame_id);
+                self.replacer.lock().reinsert(frame_id, page_id);
+                return Err(e);
+            }
         }
 
-        // if a frame is evicted to make space, remove the stale record in the page table
-        self.page_table.remove(&frame.page_id());
+        // a prefetched page may be evicted before anyone fetched it
+        frame.take_pending_read();
+        page_frame.reset();
+        StatsCounters::increment(&self.stats.evictions);
 
-        frame.reset();
+        Ok(())
+    }
 
-        Ok(frame_id)
+    /// Waits until an eviction finished writing back `page_id`, if one is writing it back.
+    /// `state` is released while waiting.
+    fn wait_for_write_back(&self, state: &mut MutexGuard<'_, PoolState>, page_id: &PageId) {
+        while state.writing_back.contains(page_id) {
+            self.frame_released.wait(state);
+        }
     }
 
-    fn create_page(&mut self) -> Result<&mut PageFrame> {
-        let new_page_id = {
-            let mut disk = self.disk_manager.write().unwrap();
-            disk.allocate_page().unwrap()
-        };
+    /// Writes a dirty page to disk, without syncing it. The checksum trailer at the end of the
+    /// page is left to the store.
+    fn write_page(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+        let data = Bytes::copy_from_slice(&data[..page_data_size(self.page_size)]);
+        DiskScheduler::wait(self.disk_scheduler.schedule_write(page_id, data))?;
+        StatsCounters::increment(&self.stats.write_backs);
+        Ok(())
+    }
 
-        let frame_id = self.get_free_frame()?;
+    /// Installs `page_id` in a free frame and pins it. The caller holds the exclusive latch
+    /// of the frame, and sets up its contents.
+    fn install_page(
+        &self,
+        state: &mut PoolState,
+        frame_id: FrameId,
+        page_frame: &mut PageFrameWriteGuard,
+        page_id: PageId,
+        access_type: AccessType,
+    ) {
+        state.page_table.insert(page_id, frame_id);
+        if access_type == AccessType::Scan && self.scan_ring_size > 0 {
+            state.scan_ring.push_back((frame_id, page_id));
+        }
+
+        page_frame.set_page_id(page_id);
+        self.frame(frame_id).set_dirty(false);
+        self.pin_frame(frame_id, page_id, access_type);
+    }
 
-        // add the new record to page table
-        self.page_table.insert(new_page_id, frame_id);
+    /// Pins a frame that holds `page_id` in the page table. A page that is accessed by anything
+    /// but a scan leaves the scan ring.
+    fn pin_cached_frame(
+        &self,
+        state: &mut PoolState,
+        frame_id: FrameId,
+        page_id: PageId,
+        access_type: AccessType,
+    ) {
+        if access_type != AccessType::Scan && self.scan_ring_size > 0 {
+            state
+                .scan_ring
+                .retain(|&(ring_frame_id, _)| ring_frame_id != frame_id);
+        }
+        self.pin_frame(frame_id, page_id, access_type);
+    }
 
-        let page_frame = &mut self.frames[frame_id];
+    /// Pins a frame and records the access.
+    fn pin_frame(&self, frame_id: FrameId, page_id: PageId, access_type: AccessType) {
+        let mut replacer = self.replacer.lock();
+        self.frame(frame_id).pin();
+        replacer.record_access(frame_id, page_id, access_type);
+        replacer.pin(frame_id);
+    }
 
-        page_frame.set_page_id(new_page_id);
-        page_frame.set_dirty(false);
-        // pin the new page in frame and record access
-        page_frame.set_pin_count(1);
-        self.replacer.record_access(frame_id);
-        self.replacer.pin(frame_id);
+    /// Unpins a frame, making it evictable once it is no longer pinned. `is_dirty` marks the
+    /// page as modified by the caller.
+    pub(crate) fn unpin_frame(&self, frame_id: FrameId, is_dirty: bool) {
+        let frame = self.frame(frame_id);
+        if is_dirty {
+            frame.set_dirty(true);
+        }
 
-        Ok(page_frame)
+        let mut replacer = self.replacer.lock();
+        if frame.unpin() == 0 {
+            if frame.take_discarded() {
+                drop(replacer);
+                self.release_discarded_frame(frame_id);
+                return;
+            }
+            replacer.unpin(frame_id);
+            drop(replacer);
+            self.notify_frame_waiters();
+        }
     }
 
-    fn fetch_page_mut(&mut self, page_id: &PageId) -> Result<&mut PageFrame> {
-        if let Some(&frame_id) = self.page_table.get(page_id) {
-            let frame = &mut self.frames[frame_id];
-            frame.increment_pin_count();
-            self.replacer.record_access(frame_id);
-            self.replacer.pin(frame_id);
-            return Ok(frame);
+    /// Puts a frame whose page failed to be read back on the free list, once its last pin is
+    /// released. The frame is no longer in the page table, so nobody pins it again meanwhile.
+    fn release_discarded_frame(&self, frame_id: FrameId) {
+        let mut state = self.state.lock();
+        {
+            let mut replacer = self.replacer.lock();
+            replacer.unpin(frame_id);
+            replacer.remove(frame_id);
         }
+        self.frame(frame_id).set_dirty(false);
+        self.release_free_frame(&mut state, frame_id);
+    }
+
+    /// Creates a new page, and returns its frame, pinned and latched in exclusive mode.
+    fn create_page(&self) -> Result<(FrameId, PageFrameWriteGuard)> {
+        let mut state = self.state.lock();
+        let frame_id = self.get_free_frame(&mut state, AccessType::Unknown)?;
 
-        let frame_id = self.get_free_frame()?;
+        let new_page_id = match self.disk_scheduler.allocate_page() {
+            Ok(page_id) => page_id,
+            Err(e) => {
+                self.release_free_frame(&mut state, frame_id);
+                return Err(e);
+            }
+        };
 
-        self.page_table.insert(*page_id, frame_id);
+        let mut page_frame = self.frame(frame_id).write();
+        self.install_page(
+            &mut state,
+            frame_id,
+            &mut page_frame,
+            new_page_id,
+            AccessType::Unknown,
+        );
 
-        let page_frame = &mut self.frames[frame_id];
-        page_frame.set_page_id(*page_id);
-        page_frame.set_dirty(false);
-        page_frame.set_pin_count(1);
+        Ok((frame_id, page_frame))
+    }
 
-        self.replacer.record_access(frame_id);
-        self.replacer.pin(frame_id);
+    /// Pins the frame holding `page_id`, reading the page from disk if it is not cached, or
+    /// finishing its read if it was prefetched.
+    ///
+    /// The page is read without holding `state`, but while holding the exclusive latch of the
+    /// frame, so other threads fetching the same page wait on the latch until it is read. If the
+    /// read fails, the frame is reset, which the callers check for once they hold the latch.
+    fn pin_page(&self, page_id: &PageId, access_type: AccessType) -> Result<FrameId> {
+        let mut state = self.state.lock();
+        let frame_id = loop {
+            self.wait_for_write_back(&mut state, page_id);
+            if let Some(&frame_id) = state.page_table.get(page_id) {
+                self.pin_cached_frame(&mut state, frame_id, *page_id, access_type);
+                StatsCounters::increment(&self.stats.hits);
+                drop(state);
 
-        let page_data = {
-            let mut disk = self.disk_manager.write()?;
-            disk.read(page_id)?
+                if let Err(e) = self.finish_pending_read(frame_id, true) {
+                    self.unpin_frame(frame_id, false);
+                    return Err(e);
+                }
+                return Ok(frame_id);
+            }
+
+            let frame_id = self.get_free_frame(&mut state, access_type)?;
+            // another thread may have brought the page in, or be evicting it again, after we
+            // waited for a frame
+            if !state.page_table.contains_key(page_id) && !state.writing_back.contains(page_id) {
+                break frame_id;
+            }
+            self.release_free_frame(&mut state, frame_id);
+        };
+        StatsCounters::increment(&self.stats.misses);
+
+        let mut page_frame = self.frame(frame_id).write();
+        self.install_page(&mut state, frame_id, &mut page_frame, *page_id, access_type);
+        drop(state);
+
+        let read = self.disk_scheduler.schedule_read(*page_id);
+        if let Err(e) = self.complete_read(frame_id, &mut page_frame, DiskScheduler::wait(read)) {
+            drop(page_frame);
+            self.unpin_frame(frame_id, false);
+            return Err(e);
+        }
+        Ok(frame_id)
+    }
+
+    /// Starts reading `page_id` into a frame in the background, so that a later fetch of the
+    /// page does not have to wait for the whole read. Does nothing if the page is cached or
+    /// every frame is pinned.
+    ///
+    /// The frame is not pinned while the read is in flight, so the page may be evicted again
+    /// before it is fetched. The fetch that pins the page first copies the read into the frame,
+    /// and reports the error if the read failed.
+    pub(crate) fn prefetch_page(&self, page_id: &PageId, access_type: AccessType) -> Result<()> {
+        let mut state = self.state.lock();
+        if state.page_table.contains_key(page_id) || state.writing_back.contains(page_id) {
+            return Ok(());
         }
-        .ok_or(Error::IO(page_id.to_string()))?;
+        let frame_id = match self.try_get_free_frame(&mut state, access_type) {
+            // the page may have been brought in while a victim was written back
+            Ok(frame_id)
+                if state.page_table.contains_key(page_id)
+                    || state.writing_back.contains(page_id) =>
+            {
+                self.release_free_frame(&mut state, frame_id);
+                return Ok(());
+            }
+            Ok(frame_id) => frame_id,
+            Err(Error::BufferPoolFull) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        StatsCounters::increment(&self.stats.prefetches);
 
-        page_frame.write(0, page_data.as_ref());
+        let frame = self.frame(frame_id);
+        let mut page_frame = frame.write();
+        self.install_page(&mut state, frame_id, &mut page_frame, *page_id, access_type);
+        frame.set_pending_read(self.disk_scheduler.schedule_read(*page_id));
+        drop(page_frame);
+        drop(state);
+        self.unpin_frame(frame_id, false);
 
-        Ok(page_frame)
+        Ok(())
     }
 
-    fn fetch_page(&mut self, page_id: &PageId) -> Result<&PageFrame> {
-        self.fetch_page_mut(page_id).map(|page| &*page)
+    /// Copies the prefetched page into a frame that the caller has pinned, if it was not
+    /// copied yet. Unless `block`, returns `Ok(false)` instead of waiting for the latch or the
+    /// read.
+    fn finish_pending_read(&self, frame_id: FrameId, block: bool) -> Result<bool> {
+        let frame = self.frame(frame_id);
+        if !frame.has_pending_read() {
+            return Ok(true);
+        }
+
+        let mut page_frame = if block {
+            self.latch_exclusive(frame_id)
+        } else {
+            match frame.try_write() {
+                Some(page_frame) => page_frame,
+                None => return Ok(false),
+            }
+        };
+        // another thread may have finished the read while we waited for the latch
+        let Some(read) = frame.take_pending_read() else {
+            return Ok(true);
+        };
+        if block {
+            self.complete_read(frame_id, &mut page_frame, DiskScheduler::wait(read))?;
+        } else {
+            let Some(result) = DiskScheduler::try_wait(&read) else {
+                frame.set_pending_read(read);
+                return Ok(false);
+            };
+            self.complete_read(frame_id, &mut page_frame, result)?;
+        }
+        Ok(true)
     }
 
-    pub(crate) fn unpin_page(&mut self, page_id: &PageId, is_dirty: bool) {
-        if let Some(&frame_id) = self.page_table.get(page_id) {
-            let page_frame = &mut self.frames[frame_id];
-            if is_dirty {
-                page_frame.set_dirty(true);
+    /// Copies the result of reading the page installed in a pinned frame into the frame. If the
+    /// read failed, the page is removed from the pool and the frame reset, and the frame is
+    /// freed once the last thread that pinned it unpins it.
+    fn complete_read(
+        &self,
+        frame_id: FrameId,
+        page_frame: &mut PageFrameWriteGuard,
+        result: Result<Option<Bytes>>,
+    ) -> Result<()> {
+        let page_id = page_frame.page_id();
+        match result.and_then(|data| data.ok_or(Error::IO(page_id.to_string()))) {
+            Ok(page_data) => {
+                page_frame.write(0, page_data.as_ref());
+                Ok(())
             }
-            page_frame.decrement_pin_count();
-            if page_frame.pin_count() == 0 {
-                self.replacer.unpin(frame_id);
+            Err(e) => {
+                let mut state = self.state.lock();
+                state.page_table.remove(&page_id);
+                state
+                    .scan_ring
+                    .retain(|&(ring_frame_id, _)| ring_frame_id != frame_id);
+                page_frame.reset();
+                self.frame(frame_id).discard();
+                Err(e)
             }
         }
     }
 
-    /// deletes page from both the bpm and disk
-    fn delete_page(&mut self, page_id: &PageId) -> Result<()> {
-        // If the page is not in the buffer pool, return true (nothing to delete)
-        if !self.page_table.contains_key(&page_id) {
-            return Ok(());
+    /// Deletes a page from both the buffer pool and disk. The page is deallocated on disk
+    /// whether or not it is cached, but a cached page that is pinned is not deleted, and
+    /// [`Error::PagePinned`] is returned instead. To delete a page that the caller holds a
+    /// handle to, use [`PageFrameMutHandle::delete`].
+    pub(crate) fn delete_page(&self, page_id: &PageId) -> Result<()> {
+        let mut state = self.state.lock();
+        self.wait_for_write_back(&mut state, page_id);
+        let Some(&frame_id) = state.page_table.get(page_id) else {
+            return self.disk_scheduler.deallocate_page(page_id);
+        };
+        if self.frame(frame_id).pin_count() > 0 {
+            return Err(Error::PagePinned { page_id: *page_id });
         }
 
-        let frame_id = self.page_table[&page_id];
-        let page_frame = &mut self.frames[frame_id];
-
-        // If the page is pinned, deletion is not possible
-        if page_frame.pin_count() > 0 {
-            // should probably return error here
-            panic!("Cannot delete page when page is pinned");
-        }
+        // the frame is not pinned, so nobody else holds its latch
+        let page_frame = self.frame(frame_id).write();
+        self.remove_page(&mut state, frame_id, page_frame)
+    }
 
-        self.replacer.unpin(frame_id);
-        self.replacer.remove(frame_id);
+    /// Deletes the page in a frame that the caller has pinned once and latched in exclusive
+    /// mode, see [`PageFrameMutHandle::delete`]. If the page is pinned by anyone else or cannot
+    /// be deallocated, the frame is unpinned and the page stays cached.
+    pub(crate) fn delete_latched_page(
+        &self,
+        frame_id: FrameId,
+        page_frame: PageFrameWriteGuard,
+    ) -> Result<()> {
+        let mut state = self.state.lock();
+        let frame = self.frame(frame_id);
 
-        // Remove page from page_table
-        self.page_table.remove(&page_id);
+        // other threads may be waiting for the latch, holding a pin
+        if frame.pin_count() > 1 {
+            let page_id = page_frame.page_id();
+            drop(state);
+            drop(page_frame);
+            self.unpin_frame(frame_id, true);
+            return Err(Error::PagePinned { page_id });
+        }
 
-        // Add the frame to the free list
-        self.free_list.push_back(frame_id);
+        // nobody else can pin the frame while we hold `state`. Like dropping the handle, this
+        // marks the page dirty, in case it stays cached.
+        frame.set_dirty(true);
+        {
+            let mut replacer = self.replacer.lock();
+            frame.unpin();
+            replacer.unpin(frame_id);
+        }
+        self.frame_released.notify_all();
+        self.remove_page(&mut state, frame_id, page_frame)
+    }
 
-        // deallocate the page on disk
-        let mut disk = self.disk_manager.write().unwrap();
-        disk.deallocate_page(page_id).unwrap();
+    /// Deallocates the page in an unpinned frame on disk and moves the frame to the free list.
+    /// If deallocating fails, the page stays cached.
+    fn remove_page(
+        &self,
+        state: &mut PoolState,
+        frame_id: FrameId,
+        mut page_frame: PageFrameWriteGuard,
+    ) -> Result<()> {
+        let page_id = page_frame.page_id();
+        let frame = self.frame(frame_id);
+        self.disk_scheduler.deallocate_page(&page_id)?;
 
-        // Reset the page's metadata and memory
+        self.replacer.lock().remove(frame_id);
+        state.page_table.remove(&page_id);
+        frame.set_dirty(false);
+        frame.take_pending_read();
         page_frame.reset();
+        drop(page_frame);
+        self.release_free_frame(state, frame_id);
 
         Ok(())
     }
 
-    fn capacity(&self) -> usize {
-        self.frames.len()
+    /// Writes `page_id` to disk if it is cached and dirty, and makes it durable.
+    pub(crate) fn flush_page(&self, page_id: &PageId) -> Result<()> {
+        self.write_back_page(page_id)?;
+        DiskScheduler::wait(self.disk_scheduler.schedule_sync())
+    }
+
+    /// Writes every dirty page to disk, and makes them durable. Pages that are modified while
+    /// the pool is flushed may or may not be written.
+    pub(crate) fn flush_all_pages(&self) -> Result<()> {
+        let page_ids: Vec<PageId> = {
+            let state = self.state.lock();
+            let page_ids = state.page_table.keys().chain(&state.writing_back);
+            page_ids.copied().collect()
+        };
+        for page_id in &page_ids {
+            self.write_back_page(page_id)?;
+        }
+        DiskScheduler::wait(self.disk_scheduler.schedule_sync())
+    }
+
+    /// Writes `page_id` to disk if it is cached and dirty, without syncing it.
+    fn write_back_page(&self, page_id: &PageId) -> Result<()> {
+        let frame_id = {
+            let mut state = self.state.lock();
+            // a page that an eviction is writing back is flushed once the eviction completes
+            self.wait_for_write_back(&mut state, page_id);
+            let Some(&frame_id) = state.page_table.get(page_id) else {
+                return Ok(());
+            };
+            self.pin_for_write_back(frame_id);
+            frame_id
+        };
+        self.write_back_pinned_frame(frame_id)
+    }
+
+    /// Writes up to `max_pages` dirty pages that are not pinned to disk, without syncing them,
+    /// and returns the number of pages written. Each call continues where the last one left
+    /// off, so repeated calls cycle through the whole pool.
+    pub(crate) fn write_back_unpinned_pages(&self, max_pages: usize) -> Result<usize> {
+        let mut frame_ids = Vec::new();
+        {
+            let _state = self.state.lock();
+            let frames = self.frames.read().clone();
+            for _ in 0..frames.len() {
+                if frame_ids.len() == max_pages {
+                    break;
+                }
+                let frame_id = self.writer_hand.fetch_add(1, Ordering::Relaxed) % frames.len();
+                let Some(frame) = &frames[frame_id] else {
+                    continue;
+                };
+                if frame.pin_count() == 0 && frame.is_dirty() {
+                    self.pin_for_write_back(frame_id);
+                    frame_ids.push(frame_id);
+                }
+            }
+        }
+
+        let mut result = Ok(frame_ids.len());
+        for frame_id in frame_ids {
+            // keep going, so that every frame pinned above is unpinned again
+            if let Err(e) = self.write_back_pinned_frame(frame_id) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Pins a cached frame so that it is not evicted while it is written back. Unlike
+    /// [`BufferPoolManager::pin_frame`], this does not count as an access of the page. The
+    /// caller holds `state`.
+    fn pin_for_write_back(&self, frame_id: FrameId) {
+        let mut replacer = self.replacer.lock();
+        self.frame(frame_id).pin();
+        replacer.pin(frame_id);
     }
 
-    fn free_frame_count(&self) -> usize {
-        self.free_list.len() + self.replacer.evictable_count()
+    /// Writes the page in a pinned frame to disk if it is dirty, and unpins the frame.
+    ///
+    /// The shared latch is held until the write completes, so the page can be read but not
+    /// modified meanwhile. This keeps an older copy of the page from landing on disk after a
+    /// newer one.
+    fn write_back_pinned_frame(&self, frame_id: FrameId) -> Result<()> {
+        let frame = self.frame(frame_id);
+
+        let page_frame = frame.read();
+        // skip pages that failed to load, or are not dirty
+        let page_id = page_frame.page_id();
+        let result = if page_id != INVALID_PAGE_ID && frame.take_dirty() {
+            self.write_page(page_id, page_frame.data())
+        } else {
+            Ok(())
+        };
+        drop(page_frame);
+
+        // a page that did not reach the disk is still dirty
+        self.unpin_frame(frame_id, result.is_err());
+        result
+    }
+
+    pub(crate) fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Returns the number of frames in the pool.
+    pub(crate) fn capacity(&self) -> usize {
+        self.frames.read().iter().flatten().count()
+    }
+
+    /// Returns the number of frames that are free or hold an unpinned page.
+    pub(crate) fn free_frame_count(&self) -> usize {
+        let state = self.state.lock();
+        state.free_list.len() + self.replacer.lock().evictable_count()
+    }
+
+    pub(crate) fn pin_count(&self, frame_id: FrameId) -> usize {
+        self.frame(frame_id).pin_count()
+    }
+
+    pub(crate) fn stats(&self) -> BufferPoolStats {
+        self.stats.snapshot()
     }
 
-    pub(crate) fn create_page_handle(
-        bpm: &Arc<RwLock<BufferPoolManager>>,
-    ) -> Result<PageFrameMutHandle> {
-        let mut bpm_guard = bpm.write().unwrap();
-        let bpm_ptr = &mut *bpm_guard as *mut BufferPoolManager;
-        let page_frame = unsafe { (*bpm_ptr).create_page()? };
+    /// Lists the page, pin count and dirty bit of every frame, e.g. to find pages that are
+    /// never unpinned. Frames are not latched, so the snapshot is only consistent if no other
+    /// thread uses the pool meanwhile.
+    pub(crate) fn frame_snapshot(&self) -> Vec<FrameInfo> {
+        let frames = self.frames.read().clone();
+        let mut page_ids = vec![None; frames.len()];
+        for (&page_id, &frame_id) in &self.state.lock().page_table {
+            page_ids[frame_id] = Some(page_id);
+        }
+
+        frames
+            .iter()
+            .zip(page_ids)
+            .enumerate()
+            .filter_map(|(frame_id, (frame, page_id))| {
+                let frame = frame.as_ref()?;
+                Some(FrameInfo {
+                    frame_id,
+                    page_id,
+                    pin_count: frame.pin_count(),
+                    is_dirty: frame.is_dirty(),
+                })
+            })
+            .collect()
+    }
 
-        Ok(PageFrameMutHandle::new(&bpm, page_frame))
+    /// Latches a pinned frame in shared mode, counting whether it has to wait for a writer.
+    fn latch_shared(&self, frame_id: FrameId) -> PageFrameReadGuard {
+        let frame = self.frame(frame_id);
+        frame.try_read().unwrap_or_else(|| {
+            StatsCounters::increment(&self.stats.pin_waits);
+            frame.read()
+        })
     }
 
-    pub(crate) fn fetch_page_handle<'a>(
-        bpm: &'a Arc<RwLock<BufferPoolManager>>,
+    /// Latches a pinned frame in exclusive mode, counting whether it has to wait for others.
+    fn latch_exclusive(&self, frame_id: FrameId) -> PageFrameWriteGuard {
+        let frame = self.frame(frame_id);
+        frame.try_write().unwrap_or_else(|| {
+            StatsCounters::increment(&self.stats.pin_waits);
+            frame.write()
+        })
+    }
+
+    pub(crate) fn create_page_handle(&self) -> Result<PageFrameMutHandle<'_>> {
+        let (frame_id, page_frame) = self.create_page()?;
+
+        Ok(PageFrameMutHandle::new(self, frame_id, page_frame))
+    }
+
+    /// Fetches a page for reading. `access_type` tells the replacer how the page is accessed,
+    /// e.g. so that a sequential scan does not evict the pages used by point lookups.
+    pub(crate) fn fetch_page_handle(
+        &self,
         page_id: &PageId,
-    ) -> Result<PageFrameRefHandle<'a>> {
-        let mut bpm_guard = bpm.write().unwrap();
-        let bpm_ptr = &mut *bpm_guard as *mut BufferPoolManager;
-        let page_frame = unsafe { (*bpm_ptr).fetch_page(&page_id)? };
+        access_type: AccessType,
+    ) -> Result<PageFrameRefHandle<'_>> {
+        loop {
+            let frame_id = self.pin_page(page_id, access_type)?;
+            let page_frame = self.latch_shared(frame_id);
+            if page_frame.page_id() == *page_id {
+                return Ok(PageFrameRefHandle::new(self, frame_id, page_frame));
+            }
 
-        Ok(PageFrameRefHandle::new(&bpm, page_frame))
+            // another thread failed to read the page while we waited for the latch
+            drop(page_frame);
+            self.unpin_frame(frame_id, false);
+        }
     }
 
-    pub(crate) fn fetch_page_mut_handle<'a>(
-        bpm: &'a Arc<RwLock<BufferPoolManager>>,
+    /// Fetches a page for reading like [`BufferPoolManager::fetch_page_handle`], but only if
+    /// that does not block. Returns `None` if the page is not cached, its prefetch has not
+    /// completed yet, or a writer holds it.
+    pub(crate) fn try_fetch_page_handle(
+        &self,
         page_id: &PageId,
-    ) -> Result<PageFrameMutHandle<'a>> {
-        let mut bpm_guard = bpm.write().unwrap();
-        let bpm_ptr = &mut *bpm_guard as *mut BufferPoolManager;
-        let page_frame = unsafe { (*bpm_ptr).fetch_page_mut(&page_id)? };
+        access_type: AccessType,
+    ) -> Option<PageFrameRefHandle<'_>> {
+        let frame_id = {
+            let mut state = self.state.lock();
+            let frame_id = *state.page_table.get(page_id)?;
+            self.pin_cached_frame(&mut state, frame_id, *page_id, access_type);
+            frame_id
+        };
 
-        Ok(PageFrameMutHandle::new(&bpm, page_frame))
+        // a failed prefetch is reported by the next fetch, which reads the page again
+        let page_frame = match self.finish_pending_read(frame_id, false) {
+            Ok(true) => self.frame(frame_id).try_read(),
+            _ => None,
+        };
+        match page_frame {
+            Some(page_frame) if page_frame.page_id() == *page_id => {
+                StatsCounters::increment(&self.stats.hits);
+                Some(PageFrameRefHandle::new(self, frame_id, page_frame))
+            }
+            page_frame => {
+                drop(page_frame);
+                self.unpin_frame(frame_id, false);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn fetch_page_mut_handle(&self, page_id: &PageId) -> Result<PageFrameMutHandle<'_>> {
+        loop {
+            let frame_id = self.pin_page(page_id, AccessType::Unknown)?;
+            let page_frame = self.latch_exclusive(frame_id);
+            if page_frame.page_id() == *page_id {
+                return Ok(PageFrameMutHandle::new(self, frame_id, page_frame));
+            }
+
+            // another thread failed to read the page while we waited for the latch
+            drop(page_frame);
+            self.unpin_frame(frame_id, false);
+        }
+    }
+}
+
+impl Drop for BufferPoolManager {
+    /// Flushes the dirty pages, so that they are not lost when the pool is dropped. Use
+    /// [`BufferPoolManager::flush_all_pages`] to handle errors.
+    fn drop(&mut self) {
+        let _ = self.flush_all_pages();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::buffer_pool::BufferPoolManager;
+    use crate::buffer_pool_stats::{BufferPoolStats, FrameInfo};
     use crate::disk::disk_manager::DiskManager;
+    use crate::disk::fault_injecting_page_store::FaultInjectingPageStore;
+    use crate::disk::memory_page_store::MemoryPageStore;
+    use crate::disk::page_store::PageStore;
+    use crate::replacer::arc_replacer::ArcReplacer;
+    use crate::replacer::clock_pro_replacer::ClockProReplacer;
+    use crate::replacer::clock_replacer::ClockReplacer;
+    use crate::replacer::lru_k_replacer::LrukReplacer;
     use crate::replacer::lru_replacer::LruReplacer;
-    use std::sync::{Arc, RwLock};
+    use crate::replacer::replacer::{AccessType, Replacer};
+    use crate::typedef::PageId;
+    use crate::Result;
+    use bytes::Bytes;
+    use parking_lot::{Condvar, Mutex};
+    use rustdb_error::Error;
+    use std::collections::HashSet;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn setup_faulty(pool_size: usize) -> (Arc<FaultInjectingPageStore>, Arc<BufferPoolManager>) {
+        let store = Arc::new(FaultInjectingPageStore::new(Arc::new(
+            MemoryPageStore::new(),
+        )));
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = BufferPoolManager::new(pool_size, store.clone(), replacer);
+        (store, Arc::new(bpm))
+    }
+
+    /// Writes to a page, evicts it by creating another page and reads it back.
+    fn evict_and_refetch(
+        page_store: Arc<dyn PageStore>,
+        replacer: Box<dyn Replacer>,
+    ) -> Result<()> {
+        let bpm = Arc::new(BufferPoolManager::new(1, page_store, replacer));
+
+        let page_id = {
+            let mut handle = BufferPoolManager::create_page_handle(&bpm)?;
+            handle.page_frame_mut().write(0, &[1, 2, 3]);
+            handle.page_frame_mut().page_id()
+        };
+
+        // The pool holds a single frame, so this evicts the first page.
+        let other_page_id = BufferPoolManager::create_page_handle(&bpm)?
+            .page_frame_mut()
+            .page_id();
+        assert_ne!(page_id, other_page_id);
+
+        let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown)?;
+        assert_eq!(&[1, 2, 3], &handle.page_frame().data()[..3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evicted_page_round_trips_through_memory_store() -> Result<()> {
+        evict_and_refetch(
+            Arc::new(MemoryPageStore::new()),
+            Box::new(LruReplacer::new()),
+        )
+    }
+
+    #[test]
+    fn test_evicted_page_round_trips_through_disk() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let disk = DiskManager::builder(dir.path().join("test.db")).open()?;
+        evict_and_refetch(Arc::new(disk), Box::new(LruReplacer::new()))
+    }
+
+    #[test]
+    fn test_evicted_page_round_trips_with_each_replacer() -> Result<()> {
+        let replacers: Vec<Box<dyn Replacer>> = vec![
+            Box::new(LrukReplacer::new(2)),
+            Box::new(ClockReplacer::new(1)),
+            Box::new(ClockProReplacer::new(1)),
+            Box::new(ArcReplacer::new(1)),
+        ];
+        for replacer in replacers {
+            evict_and_refetch(Arc::new(MemoryPageStore::new()), replacer)?;
+        }
+        Ok(())
+    }
 
     #[test]
     fn test_create_pages_beyond_capacity() {
         let pool_size = 5;
-        let disk = Arc::new(RwLock::new(DiskManager::new("test.db").unwrap()));
+        let disk = Arc::new(MemoryPageStore::new());
         let replacer = Box::new(LruReplacer::new());
-        let bpm = Arc::new(RwLock::new(BufferPoolManager::new(
-            pool_size, disk, replacer,
-        )));
+        let bpm = Arc::new(BufferPoolManager::new(pool_size, disk, replacer));
 
-        assert_eq!(pool_size, bpm.read().unwrap().free_frame_count());
+        assert_eq!(pool_size, bpm.free_frame_count());
 
         {
             let mut handles = vec![];
@@ -241,10 +1090,10 @@ mod tests {
                 let page_handle = BufferPoolManager::create_page_handle(&bpm);
                 assert!(page_handle.is_ok());
                 handles.push(page_handle);
-                assert_eq!(pool_size - i - 1, bpm.read().unwrap().free_frame_count());
+                assert_eq!(pool_size - i - 1, bpm.free_frame_count());
             }
 
-            assert_eq!(0, bpm.read().unwrap().free_frame_count());
+            assert_eq!(0, bpm.free_frame_count());
 
             {
                 // Create a new page when buffer pool has no free frame, should return None
@@ -253,11 +1102,843 @@ mod tests {
             }
 
             handles.pop();
-            assert_eq!(1, bpm.read().unwrap().free_frame_count());
+            assert_eq!(1, bpm.free_frame_count());
 
             let page_handle = BufferPoolManager::create_page_handle(&bpm);
             assert!(page_handle.is_ok());
         }
-        assert_eq!(5, bpm.read().unwrap().free_frame_count());
+        assert_eq!(5, bpm.free_frame_count());
+    }
+
+    #[test]
+    fn test_failed_eviction_write_keeps_page_cached() -> Result<()> {
+        let (store, bpm) = setup_faulty(1);
+
+        let page_id = {
+            let mut handle = BufferPoolManager::create_page_handle(&bpm)?;
+            handle.page_frame_mut().write(0, &[1, 2, 3]);
+            handle.page_frame_mut().page_id()
+        };
+
+        // Evicting the dirty page fails, so no new page can be created.
+        store.fail_nth_write(1);
+        assert!(matches!(
+            BufferPoolManager::create_page_handle(&bpm),
+            Err(Error::IO(_))
+        ));
+        assert_eq!(1, bpm.free_frame_count());
+
+        // The page is still cached and dirty, and is written out by the next eviction.
+        {
+            let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown)?;
+            assert_eq!(&[1, 2, 3], &handle.page_frame().data()[..3]);
+        }
+        BufferPoolManager::create_page_handle(&bpm)?;
+        let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown)?;
+        assert_eq!(&[1, 2, 3], &handle.page_frame().data()[..3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_read_releases_frame() -> Result<()> {
+        let (store, bpm) = setup_faulty(1);
+
+        let page_id = BufferPoolManager::create_page_handle(&bpm)?
+            .page_frame_mut()
+            .page_id();
+        BufferPoolManager::create_page_handle(&bpm)?;
+
+        store.fail_nth_read(1);
+        assert!(matches!(
+            BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown),
+            Err(Error::IO(_))
+        ));
+        assert_eq!(1, bpm.free_frame_count());
+        assert!(!bpm.state.lock().page_table.contains_key(&page_id));
+
+        BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shrink_after_failed_read() -> Result<()> {
+        let (store, bpm) = setup_faulty(2);
+        let page_ids = create_pages(&bpm, 3)?;
+
+        store.fail_nth_read(1);
+        assert!(matches!(
+            BufferPoolManager::fetch_page_handle(&bpm, &page_ids[0], AccessType::Unknown),
+            Err(Error::IO(_))
+        ));
+        assert_eq!(1, bpm.state.lock().free_list.len());
+
+        bpm.resize(0)?;
+        assert_eq!(0, bpm.capacity());
+        bpm.resize(2)?;
+        assert_pages_hold_their_index(&bpm, &page_ids)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_crash_returns_io_errors() -> Result<()> {
+        let (store, bpm) = setup_faulty(1);
+
+        let page_id = {
+            let mut handle = BufferPoolManager::create_page_handle(&bpm)?;
+            handle.page_frame_mut().write(0, &[1]);
+            handle.page_frame_mut().page_id()
+        };
+
+        store.crash();
+        assert!(matches!(
+            BufferPoolManager::create_page_handle(&bpm),
+            Err(Error::IO(_))
+        ));
+
+        // The cached page can still be read, but nothing reaches the store anymore.
+        let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown)?;
+        assert_eq!(1, handle.page_frame().data()[0]);
+        assert_eq!(0, store.inner().read(&page_id)?.unwrap()[0]);
+
+        Ok(())
+    }
+
+    /// Creates `page_count` pages holding their index in their first byte, and returns their ids.
+    fn create_pages(bpm: &Arc<BufferPoolManager>, page_count: usize) -> Result<Vec<PageId>> {
+        (0..page_count)
+            .map(|i| {
+                let mut handle = BufferPoolManager::create_page_handle(bpm)?;
+                handle.page_frame_mut().write(0, &[i as u8]);
+                Ok(handle.page_frame_mut().page_id())
+            })
+            .collect()
+    }
+
+    fn cached_pages(bpm: &Arc<BufferPoolManager>) -> HashSet<PageId> {
+        bpm.state.lock().page_table.keys().copied().collect()
+    }
+
+    #[test]
+    fn test_scan_ring_limits_frames_used_by_scans() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(4, disk, replacer).with_scan_ring_size(2));
+        let page_ids = create_pages(&bpm, 8)?;
+
+        // pages 0 and 1 are the working set
+        for page_id in &page_ids[..2] {
+            BufferPoolManager::fetch_page_handle(&bpm, page_id, AccessType::Lookup)?;
+        }
+
+        for (i, page_id) in page_ids.iter().enumerate().skip(2) {
+            let handle = BufferPoolManager::fetch_page_handle(&bpm, page_id, AccessType::Scan)?;
+            assert_eq!(i as u8, handle.page_frame().data()[0]);
+        }
+
+        // the scan only ever used the two frames of the ring
+        let expected = HashSet::from([0, 1, 6, 7].map(|i| page_ids[i]));
+        assert_eq!(expected, cached_pages(&bpm));
+        assert_eq!(2, bpm.state.lock().scan_ring.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_ring_skips_pages_used_by_lookups() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(4, disk, replacer).with_scan_ring_size(2));
+        let page_ids = create_pages(&bpm, 8)?;
+
+        for page_id in &page_ids[..4] {
+            BufferPoolManager::fetch_page_handle(&bpm, page_id, AccessType::Scan)?;
+        }
+        // a lookup takes page 3 out of the ring, while page 2 stays pinned
+        BufferPoolManager::fetch_page_handle(&bpm, &page_ids[3], AccessType::Lookup)?;
+        let _pinned = BufferPoolManager::fetch_page_handle(&bpm, &page_ids[2], AccessType::Scan)?;
+
+        for page_id in &page_ids[4..] {
+            BufferPoolManager::fetch_page_handle(&bpm, page_id, AccessType::Scan)?;
+        }
+
+        let cached = cached_pages(&bpm);
+        assert!(cached.contains(&page_ids[2]));
+        assert!(cached.contains(&page_ids[3]));
+        assert!(cached.contains(&page_ids[7]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_threads_update_different_pages_concurrently() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(4, disk, replacer));
+        // twice as many pages as frames, so the threads keep evicting each other's pages
+        let page_ids = create_pages(&bpm, 8)?;
+
+        let threads: Vec<_> = page_ids
+            .chunks(2)
+            .map(|chunk| {
+                let bpm = bpm.clone();
+                let chunk = chunk.to_vec();
+                thread::spawn(move || -> Result<()> {
+                    for _ in 0..100 {
+                        for page_id in &chunk {
+                            let mut handle =
+                                BufferPoolManager::fetch_page_mut_handle(&bpm, page_id)?;
+                            handle.page_frame_mut().data_mut()[1] += 1;
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap()?;
+        }
+
+        for (i, page_id) in page_ids.iter().enumerate() {
+            let handle = BufferPoolManager::fetch_page_handle(&bpm, page_id, AccessType::Unknown)?;
+            assert_eq!(&[i as u8, 100], &handle.page_frame().data()[..2]);
+        }
+        assert_eq!(4, bpm.free_frame_count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_page_makes_page_durable() -> Result<()> {
+        let (store, bpm) = setup_faulty(2);
+        let page_ids = create_pages(&bpm, 2)?;
+        {
+            let mut handle = BufferPoolManager::fetch_page_mut_handle(&bpm, &page_ids[0])?;
+            handle.page_frame_mut().write(1, &[1, 2, 3]);
+        }
+
+        bpm.flush_page(&page_ids[0])?;
+        store.crash();
+
+        assert_eq!(
+            &[0, 1, 2, 3],
+            &store.inner().read(&page_ids[0])?.unwrap()[..4]
+        );
+        // only the flushed page reached the disk
+        assert_eq!(0, store.inner().read(&page_ids[1])?.unwrap()[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_flush_keeps_page_dirty() -> Result<()> {
+        let (store, bpm) = setup_faulty(4);
+        let page_ids = create_pages(&bpm, 4)?;
+
+        store.fail_nth_write(2);
+        assert!(matches!(bpm.flush_all_pages(), Err(Error::IO(_))));
+
+        bpm.flush_all_pages()?;
+        store.crash();
+        for (i, page_id) in page_ids.iter().enumerate() {
+            assert_eq!(i as u8, store.inner().read(page_id)?.unwrap()[0]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_flushes_dirty_pages() -> Result<()> {
+        let (store, bpm) = setup_faulty(2);
+        let page_ids = create_pages(&bpm, 2)?;
+
+        drop(bpm);
+        store.crash();
+        for (i, page_id) in page_ids.iter().enumerate() {
+            assert_eq!(i as u8, store.inner().read(page_id)?.unwrap()[0]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_written_back_pages_are_evicted_without_writes() -> Result<()> {
+        let (store, bpm) = setup_faulty(2);
+        create_pages(&bpm, 2)?;
+
+        assert_eq!(2, bpm.write_back_unpinned_pages(4)?);
+        assert_eq!(0, bpm.write_back_unpinned_pages(4)?);
+
+        // both victims are clean, so evicting them does not hit the failing write
+        store.fail_nth_write(1);
+        create_pages(&bpm, 2)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_count_fetches_and_evictions() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(2, disk, replacer));
+
+        // the third page evicts the dirty first page
+        let page_ids = create_pages(&bpm, 3)?;
+        BufferPoolManager::fetch_page_handle(&bpm, &page_ids[2], AccessType::Unknown)?;
+        // the first page is read back, evicting the dirty second page
+        BufferPoolManager::fetch_page_handle(&bpm, &page_ids[0], AccessType::Unknown)?;
+
+        let expected = BufferPoolStats {
+            hits: 1,
+            misses: 1,
+            evictions: 2,
+            write_backs: 2,
+            pin_waits: 0,
+            prefetches: 0,
+        };
+        assert_eq!(expected, bpm.stats());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_frame_snapshot() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(2, disk, replacer));
+
+        let page_id = create_pages(&bpm, 1)?[0];
+        let _handle = BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown)?;
+
+        let expected = vec![
+            FrameInfo {
+                frame_id: 0,
+                page_id: Some(page_id),
+                pin_count: 1,
+                is_dirty: true,
+            },
+            FrameInfo {
+                frame_id: 1,
+                page_id: None,
+                pin_count: 0,
+                is_dirty: false,
+            },
+        ];
+        assert_eq!(expected, bpm.frame_snapshot());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_waits_for_unpinned_frame() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(
+            BufferPoolManager::new(1, disk, replacer).with_wait_timeout(Duration::from_secs(5)),
+        );
+        let page_ids = create_pages(&bpm, 2)?;
+
+        let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_ids[0], AccessType::Unknown)?;
+        let fetcher = {
+            let bpm = bpm.clone();
+            let page_id = page_ids[1];
+            thread::spawn(move || -> Result<u8> {
+                let handle =
+                    BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown)?;
+                Ok(handle.page_frame().data()[0])
+            })
+        };
+
+        while bpm.frame_waiters.load(Ordering::SeqCst) == 0 {
+            thread::yield_now();
+        }
+        drop(handle);
+        assert_eq!(1, fetcher.join().unwrap()?);
+        assert_eq!(1, bpm.stats().pin_waits);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_for_frame_times_out() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let timeout = Duration::from_millis(20);
+        let bpm = Arc::new(BufferPoolManager::new(1, disk, replacer).with_wait_timeout(timeout));
+
+        let _handle = BufferPoolManager::create_page_handle(&bpm)?;
+        let start = Instant::now();
+        assert!(matches!(
+            BufferPoolManager::create_page_handle(&bpm),
+            Err(Error::BufferPoolFull)
+        ));
+        assert!(start.elapsed() >= timeout);
+
+        Ok(())
+    }
+
+    fn setup_memory(pool_size: usize) -> (Arc<MemoryPageStore>, Arc<BufferPoolManager>) {
+        let store = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = BufferPoolManager::new(pool_size, store.clone(), replacer);
+        (store, Arc::new(bpm))
+    }
+
+    #[test]
+    fn test_delete_page() -> Result<()> {
+        let (store, bpm) = setup_memory(2);
+        let page_ids = create_pages(&bpm, 2)?;
+
+        BufferPoolManager::delete_page(&bpm, &page_ids[0])?;
+        assert_eq!(1, store.page_count());
+        assert!(!cached_pages(&bpm).contains(&page_ids[0]));
+        assert_eq!(2, bpm.free_frame_count());
+
+        // deleting a page that is not cached still deallocates it
+        create_pages(&bpm, 2)?;
+        assert!(!cached_pages(&bpm).contains(&page_ids[1]));
+        BufferPoolManager::delete_page(&bpm, &page_ids[1])?;
+        assert_eq!(2, store.page_count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_pinned_page_fails() -> Result<()> {
+        let (store, bpm) = setup_memory(2);
+        let page_id = create_pages(&bpm, 1)?[0];
+
+        let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown)?;
+        assert_eq!(
+            Err(Error::PagePinned { page_id }),
+            BufferPoolManager::delete_page(&bpm, &page_id)
+        );
+        assert_eq!(1, store.page_count());
+        assert_eq!(0, handle.page_frame().data()[0]);
+
+        drop(handle);
+        BufferPoolManager::delete_page(&bpm, &page_id)?;
+        assert_eq!(0, store.page_count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_page_through_handle() -> Result<()> {
+        let (store, bpm) = setup_memory(2);
+        let page_id = create_pages(&bpm, 1)?[0];
+
+        BufferPoolManager::fetch_page_mut_handle(&bpm, &page_id)?.delete()?;
+        assert_eq!(0, store.page_count());
+        assert_eq!(2, bpm.free_frame_count());
+        assert!(cached_pages(&bpm).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_page_through_handle_while_pinned_fails() -> Result<()> {
+        let (store, bpm) = setup_memory(2);
+        let page_id = create_pages(&bpm, 1)?[0];
+
+        let mut handle = BufferPoolManager::fetch_page_mut_handle(&bpm, &page_id)?;
+        handle.page_frame_mut().write(0, &[7]);
+        let reader = {
+            let bpm = bpm.clone();
+            thread::spawn(move || -> Result<u8> {
+                let handle =
+                    BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown)?;
+                Ok(handle.page_frame().data()[0])
+            })
+        };
+
+        // the reader pins the page while it waits for the latch
+        while bpm.frame_snapshot()[0].pin_count < 2 {
+            thread::yield_now();
+        }
+        assert_eq!(Err(Error::PagePinned { page_id }), handle.delete());
+        assert_eq!(7, reader.join().unwrap()?);
+        assert_eq!(1, store.page_count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefetched_page_is_fetched() -> Result<()> {
+        let (_store, bpm) = setup_faulty(2);
+        let page_ids = create_pages(&bpm, 4)?;
+
+        bpm.prefetch_page(&page_ids[0], AccessType::Scan)?;
+        // prefetching a cached page does nothing
+        bpm.prefetch_page(&page_ids[3], AccessType::Scan)?;
+        assert_eq!(1, bpm.stats().prefetches);
+        assert!(cached_pages(&bpm).contains(&page_ids[0]));
+
+        // the page was read by the prefetch, so fetching it is a hit
+        let before = bpm.stats();
+        let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_ids[0], AccessType::Scan)?;
+        assert_eq!(0, handle.page_frame().data()[0]);
+        assert_eq!(before.hits + 1, bpm.stats().hits);
+        assert_eq!(before.misses, bpm.stats().misses);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_prefetch_is_reported_by_fetch() -> Result<()> {
+        let (store, bpm) = setup_faulty(2);
+        let page_ids = create_pages(&bpm, 4)?;
+
+        store.fail_nth_read(1);
+        bpm.prefetch_page(&page_ids[0], AccessType::Unknown)?;
+        assert!(matches!(
+            BufferPoolManager::fetch_page_handle(&bpm, &page_ids[0], AccessType::Unknown),
+            Err(Error::IO(_))
+        ));
+        assert!(!cached_pages(&bpm).contains(&page_ids[0]));
+
+        let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_ids[0], AccessType::Unknown)?;
+        assert_eq!(0, handle.page_frame().data()[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefetched_page_can_be_evicted_before_fetch() -> Result<()> {
+        let (_store, bpm) = setup_faulty(1);
+        let page_ids = create_pages(&bpm, 2)?;
+
+        bpm.prefetch_page(&page_ids[0], AccessType::Unknown)?;
+        let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_ids[1], AccessType::Unknown)?;
+        assert_eq!(1, handle.page_frame().data()[0]);
+        drop(handle);
+
+        let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_ids[0], AccessType::Unknown)?;
+        assert_eq!(0, handle.page_frame().data()[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_fetch_page_handle() -> Result<()> {
+        let (_store, bpm) = setup_faulty(2);
+        let page_ids = create_pages(&bpm, 3)?;
+
+        assert!(bpm
+            .try_fetch_page_handle(&page_ids[0], AccessType::Unknown)
+            .is_none());
+
+        let writer = BufferPoolManager::fetch_page_mut_handle(&bpm, &page_ids[2])?;
+        assert!(bpm
+            .try_fetch_page_handle(&page_ids[2], AccessType::Unknown)
+            .is_none());
+        drop(writer);
+
+        bpm.prefetch_page(&page_ids[0], AccessType::Unknown)?;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let handle = loop {
+            if let Some(handle) = bpm.try_fetch_page_handle(&page_ids[0], AccessType::Unknown) {
+                break handle;
+            }
+            assert!(Instant::now() < deadline, "Prefetch did not complete");
+            thread::yield_now();
+        };
+        assert_eq!(0, handle.page_frame().data()[0]);
+
+        Ok(())
+    }
+
+    fn assert_pages_hold_their_index(
+        bpm: &Arc<BufferPoolManager>,
+        page_ids: &[PageId],
+    ) -> Result<()> {
+        for (i, page_id) in page_ids.iter().enumerate() {
+            let handle = BufferPoolManager::fetch_page_handle(bpm, page_id, AccessType::Unknown)?;
+            assert_eq!(i as u8, handle.page_frame().data()[0]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_resize_with_each_replacer() -> Result<()> {
+        let replacers: Vec<Box<dyn Replacer>> = vec![
+            Box::new(LruReplacer::new()),
+            Box::new(LrukReplacer::new(2)),
+            Box::new(ClockReplacer::new(2)),
+            Box::new(ClockProReplacer::new(2)),
+            Box::new(ArcReplacer::new(2)),
+        ];
+        for replacer in replacers {
+            let disk = Arc::new(MemoryPageStore::new());
+            let bpm = Arc::new(BufferPoolManager::new(2, disk, replacer));
+
+            bpm.resize(4)?;
+            assert_eq!(4, bpm.capacity());
+            let page_ids = create_pages(&bpm, 8)?;
+            assert_pages_hold_their_index(&bpm, &page_ids)?;
+
+            bpm.resize(1)?;
+            assert_eq!(1, bpm.capacity());
+            assert_eq!(1, bpm.frame_snapshot().len());
+            assert_pages_hold_their_index(&bpm, &page_ids)?;
+
+            bpm.resize(3)?;
+            assert_eq!(3, bpm.free_frame_count());
+            assert_pages_hold_their_index(&bpm, &page_ids)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_replacer_is_sized_to_the_pool() -> Result<()> {
+        let replacers: Vec<Box<dyn Replacer>> = vec![
+            Box::new(ClockReplacer::new(2)),
+            Box::new(ClockProReplacer::new(2)),
+            Box::new(ArcReplacer::new(2)),
+        ];
+        for replacer in replacers {
+            let disk = Arc::new(MemoryPageStore::new());
+            let bpm = Arc::new(BufferPoolManager::new(4, disk, replacer));
+
+            let page_ids = create_pages(&bpm, 8)?;
+            assert_pages_hold_their_index(&bpm, &page_ids)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_grow_wakes_up_waiting_fetch() -> Result<()> {
+        let (_store, bpm) = setup_faulty(1);
+        let bpm = Arc::new(
+            Arc::into_inner(bpm)
+                .unwrap()
+                .with_wait_timeout(Duration::from_secs(5)),
+        );
+        let _handle = BufferPoolManager::create_page_handle(&bpm)?;
+
+        let creator = {
+            let bpm = bpm.clone();
+            thread::spawn(move || BufferPoolManager::create_page_handle(&bpm).map(|_| ()))
+        };
+        while bpm.frame_waiters.load(Ordering::SeqCst) == 0 {
+            thread::yield_now();
+        }
+        bpm.resize(2)?;
+        creator.join().unwrap()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shrink_fails_when_too_many_pages_are_pinned() -> Result<()> {
+        let (_store, bpm) = setup_faulty(4);
+        let page_ids = create_pages(&bpm, 4)?;
+        let handles = page_ids[..3]
+            .iter()
+            .map(|page_id| BufferPoolManager::fetch_page_handle(&bpm, page_id, AccessType::Unknown))
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(Err(Error::BufferPoolFull), bpm.resize(2));
+        assert_eq!(4, bpm.capacity());
+        assert_eq!(4, cached_pages(&bpm).len());
+
+        bpm.resize(3)?;
+        assert_eq!(3, bpm.capacity());
+        assert_eq!(
+            page_ids[..3].iter().copied().collect::<HashSet<_>>(),
+            cached_pages(&bpm)
+        );
+
+        drop(handles);
+        assert_pages_hold_their_index(&bpm, &page_ids)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shrink_keeps_pinned_frames() -> Result<()> {
+        let (_store, bpm) = setup_faulty(3);
+        let page_ids = create_pages(&bpm, 3)?;
+        let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_ids[2], AccessType::Unknown)?;
+
+        // the pinned page stays in the last frame, the slots before it are retired
+        bpm.resize(1)?;
+        let frame_ids: Vec<_> = bpm
+            .frame_snapshot()
+            .iter()
+            .map(|frame| frame.frame_id)
+            .collect();
+        assert_eq!(vec![2], frame_ids);
+
+        bpm.resize(3)?;
+        let frame_ids: Vec<_> = bpm
+            .frame_snapshot()
+            .iter()
+            .map(|frame| frame.frame_id)
+            .collect();
+        assert_eq!(vec![0, 1, 2], frame_ids);
+        assert_eq!(2, handle.page_frame().data()[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_write_back_during_shrink() -> Result<()> {
+        let (store, bpm) = setup_faulty(2);
+        let page_ids = create_pages(&bpm, 2)?;
+
+        // the first page is written back, the second one stays cached
+        store.fail_nth_write(2);
+        assert!(matches!(bpm.resize(0), Err(Error::IO(_))));
+        assert_eq!(2, bpm.capacity());
+        assert_eq!(1, bpm.state.lock().free_list.len());
+
+        bpm.resize(0)?;
+        assert_eq!(0, bpm.capacity());
+        bpm.resize(1)?;
+        assert_pages_hold_their_index(&bpm, &page_ids)?;
+
+        Ok(())
+    }
+
+    /// A page store whose writes block while it is held.
+    struct HeldPageStore {
+        inner: MemoryPageStore,
+        held: Mutex<bool>,
+        released: Condvar,
+    }
+
+    impl HeldPageStore {
+        fn new() -> Self {
+            Self {
+                inner: MemoryPageStore::new(),
+                held: Mutex::new(false),
+                released: Condvar::new(),
+            }
+        }
+
+        fn hold(&self) {
+            *self.held.lock() = true;
+        }
+
+        fn release(&self) {
+            *self.held.lock() = false;
+            self.released.notify_all();
+        }
+    }
+
+    impl PageStore for HeldPageStore {
+        fn allocate_page(&self) -> Result<PageId> {
+            self.inner.allocate_page()
+        }
+
+        fn deallocate_page(&self, page_id: &PageId) -> Result<()> {
+            self.inner.deallocate_page(page_id)
+        }
+
+        fn read(&self, page_id: &PageId) -> Result<Option<Bytes>> {
+            self.inner.read(page_id)
+        }
+
+        fn write(&self, page_id: &PageId, data: &[u8]) -> Result<()> {
+            let mut held = self.held.lock();
+            while *held {
+                self.released.wait(&mut held);
+            }
+            self.inner.write(page_id, data)
+        }
+
+        fn sync(&self) -> Result<()> {
+            self.inner.sync()
+        }
+
+        fn page_size(&self) -> usize {
+            self.inner.page_size()
+        }
+    }
+
+    #[test]
+    fn test_eviction_writes_back_without_holding_state() -> Result<()> {
+        let store = Arc::new(HeldPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(2, store.clone(), replacer));
+        let page_ids = create_pages(&bpm, 2)?;
+
+        // evicting the first page blocks in its write-back
+        store.hold();
+        let creator = {
+            let bpm = bpm.clone();
+            thread::spawn(move || -> Result<()> {
+                BufferPoolManager::create_page_handle(&bpm)?;
+                Ok(())
+            })
+        };
+        while !bpm.state.lock().writing_back.contains(&page_ids[0]) {
+            thread::yield_now();
+        }
+
+        // other pages can be fetched meanwhile, while the evicted page waits for its write
+        let handle = BufferPoolManager::fetch_page_handle(&bpm, &page_ids[1], AccessType::Unknown)?;
+        assert_eq!(1, handle.page_frame().data()[0]);
+        drop(handle);
+        let fetcher = {
+            let bpm = bpm.clone();
+            let page_id = page_ids[0];
+            thread::spawn(move || -> Result<u8> {
+                let handle =
+                    BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown)?;
+                Ok(handle.page_frame().data()[0])
+            })
+        };
+
+        store.release();
+        creator.join().unwrap()?;
+        assert_eq!(0, fetcher.join().unwrap()?);
+        assert!(bpm.state.lock().writing_back.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shrink_fails_when_pages_are_pinned_during_write_back() -> Result<()> {
+        let store = Arc::new(HeldPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(3, store.clone(), replacer));
+        let page_ids = create_pages(&bpm, 3)?;
+
+        // the other pages are pinned while the shrink writes back the first page it evicts
+        store.hold();
+        let resizer = {
+            let bpm = bpm.clone();
+            thread::spawn(move || bpm.resize(1))
+        };
+        let evicted = loop {
+            if let Some(&page_id) = bpm.state.lock().writing_back.iter().next() {
+                break page_id;
+            }
+            thread::yield_now();
+        };
+        let handles = page_ids
+            .iter()
+            .filter(|&&page_id| page_id != evicted)
+            .map(|page_id| BufferPoolManager::fetch_page_handle(&bpm, page_id, AccessType::Unknown))
+            .collect::<Result<Vec<_>>>()?;
+        store.release();
+
+        assert!(matches!(
+            resizer.join().unwrap(),
+            Err(Error::BufferPoolFull)
+        ));
+        assert_eq!(3, bpm.capacity());
+        assert_eq!(1, bpm.state.lock().free_list.len());
+
+        drop(handles);
+        bpm.resize(1)?;
+        assert_eq!(1, bpm.capacity());
+        assert_pages_hold_their_index(&bpm, &page_ids)?;
+
+        Ok(())
     }
 }
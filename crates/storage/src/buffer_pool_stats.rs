@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::typedef::{FrameId, PageId};
+
+/// Counters of the buffer pool, since it was created. See [`BufferPoolStats`].
+#[derive(Default)]
+pub(crate) struct StatsCounters {
+    pub(crate) hits: AtomicU64,
+    pub(crate) misses: AtomicU64,
+    pub(crate) evictions: AtomicU64,
+    pub(crate) write_backs: AtomicU64,
+    pub(crate) pin_waits: AtomicU64,
+    pub(crate) prefetches: AtomicU64,
+}
+
+impl StatsCounters {
+    pub(crate) fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            write_backs: self.write_backs.load(Ordering::Relaxed),
+            pin_waits: self.pin_waits.load(Ordering::Relaxed),
+            prefetches: self.prefetches.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A snapshot of the buffer pool's counters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    /// Fetches of pages that were cached.
+    pub hits: u64,
+    /// Fetches of pages that had to be read from disk.
+    pub misses: u64,
+    /// Pages evicted to make room for another page.
+    pub evictions: u64,
+    /// Dirty pages written to disk, by eviction, flushing or the background writer.
+    pub write_backs: u64,
+    /// Fetches that had to wait for another thread to release their page, or to unpin a page
+    /// when every frame was pinned.
+    pub pin_waits: u64,
+    /// Pages read ahead of their fetch by [`BufferPoolManager::prefetch_page`].
+    ///
+    /// [`BufferPoolManager::prefetch_page`]: crate::buffer_pool::BufferPoolManager::prefetch_page
+    pub prefetches: u64,
+}
+
+impl BufferPoolStats {
+    /// Returns the fraction of fetches that were hits, or `None` if nothing was fetched.
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let fetches = self.hits + self.misses;
+        (fetches > 0).then(|| self.hits as f64 / fetches as f64)
+    }
+}
+
+/// The state of a frame at the time of a [`BufferPoolManager::frame_snapshot`].
+///
+/// [`BufferPoolManager::frame_snapshot`]: crate::buffer_pool::BufferPoolManager::frame_snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+    pub frame_id: FrameId,
+    /// The page held by the frame, or `None` if the frame is free.
+    pub page_id: Option<PageId>,
+    pub pin_count: usize,
+    pub is_dirty: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_ratio() {
+        assert_eq!(None, BufferPoolStats::default().hit_ratio());
+
+        let stats = BufferPoolStats {
+            hits: 3,
+            misses: 1,
+            ..Default::default()
+        };
+        assert_eq!(Some(0.75), stats.hit_ratio());
+    }
+}
@@ -1,106 +1,870 @@
+use crate::disk::page_store::PageStore;
+use crate::page::{validate_page_size, DEFAULT_PAGE_SIZE, INVALID_PAGE_ID};
 use crate::typedef::PageId;
 use crate::Result;
+use bytemuck::{Pod, Zeroable};
 use bytes::{Bytes, BytesMut};
-use rustdb_error::{errdata, Error};
-use std::cell::{RefCell, RefMut};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use rustdb_error::{errdata, errinput, Error};
+use std::collections::HashSet;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 pub(crate) const DATA_DIR: &str = "src/disk/data/";
-const PAGE_SIZE_BYTES: usize = 4096;
+/// Every page ends with a CRC32C checksum of the bytes before it, stamped on write and verified
+/// on read. Page layouts must leave these trailing bytes unused.
+pub(crate) const PAGE_CHECKSUM_SIZE: usize = std::mem::size_of::<u32>();
 
-const DELETED_FLAG: &'static [u8] = &[1];
-const EMPTY_BUFFER: &'static [u8] = &[0; PAGE_SIZE_BYTES];
+/// Magic bytes identifying a database file, stored at the start of page 0.
+const MAGIC: [u8; 8] = *b"RUSTDB\0\0";
+/// Version of the on-disk format. Bump whenever the file layout changes.
+const FORMAT_VERSION: u32 = 3;
+/// The page reserved for the file header. It is never handed out by `allocate_page`.
+const HEADER_PAGE_ID: PageId = 0;
 
-/// Handles read and write accesses to pages stored on disk. File I/O operations are synchronous.
-/// Asynchronous row operations, on the other hand, should occur on the pages buffered in memory,
-/// with the disk manager being protected behind a [tokio::sync::RwLock] synchronization primitive.
+/// Header stored on page 0 of every database file.
+#[repr(C)]
+#[derive(Pod, Zeroable, Copy, Clone, Debug)]
+struct FileHeader {
+    magic: [u8; 8],
+    version: u32,
+    /// Size of every page of the file, chosen when the file is created.
+    page_size: u32,
+    /// First page of the free page list, or `INVALID_PAGE_ID` if no page is free.
+    free_list_head: u64,
+}
+
+pub(crate) const FILE_HEADER_SIZE: usize = std::mem::size_of::<FileHeader>();
+
+impl FileHeader {
+    fn new(page_size: usize, free_list_head: PageId) -> Self {
+        Self {
+            magic: MAGIC,
+            version: FORMAT_VERSION,
+            page_size: page_size as u32,
+            free_list_head: free_list_head as u64,
+        }
+    }
+
+    /// Checks that the header was written by a compatible disk manager, and that its page size
+    /// is `expected_page_size` if one is given.
+    fn validate(&self, expected_page_size: Option<usize>) -> Result<()> {
+        if self.magic != MAGIC {
+            return errdata!("Not a database file: bad magic bytes {:?}.", self.magic);
+        }
+        if self.version != FORMAT_VERSION {
+            return errdata!(
+                "Unsupported format version {}, expected {}.",
+                self.version,
+                FORMAT_VERSION
+            );
+        }
+        if validate_page_size(self.page_size as usize).is_err() {
+            return errdata!("Invalid page size {}.", self.page_size);
+        }
+        if let Some(expected) = expected_page_size.filter(|&size| size != self.page_size as usize) {
+            return errdata!(
+                "Page size mismatch: file uses {} bytes, expected {}.",
+                self.page_size,
+                expected
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Header stored at the start of every deallocated page, chaining the free pages into a
+/// singly linked list that starts at [`FileHeader::free_list_head`].
+#[repr(C)]
+#[derive(Pod, Zeroable, Copy, Clone, Debug)]
+struct FreePageHeader {
+    next_free_page_id: u64,
+}
+
+/// Tracks which pages of the file are in use.
+#[derive(Debug, Default)]
+struct PageAllocator {
+    last_allocated_pid: PageId,
+    /// Deallocated pages waiting to be reused, the last entry is the head of the on-disk list.
+    free_list: Vec<PageId>,
+    /// The same pages as `free_list`, for quick membership checks on reads.
+    free_pages: HashSet<PageId>,
+}
+
+impl PageAllocator {
+    fn free_list_head(&self) -> PageId {
+        self.free_list.last().copied().unwrap_or(INVALID_PAGE_ID)
+    }
+}
+
+/// Handles read and write accesses to pages stored on disk. File I/O operations are synchronous,
+/// the buffer pool issues them through a [`DiskScheduler`] to keep them off its critical path.
+///
+/// Pages are accessed with positional reads and writes, so a disk manager shared behind an
+/// `Arc` serves concurrent reads and writes of different pages without locking. Only page
+/// allocation is serialized.
+///
+/// Page writes are not durable until [`DiskManager::sync`] is called. The same goes for the
+/// file header and free page list updated by allocating and deallocating pages, so creating
+/// many pages and flushing them costs a single `fsync`.
+///
+/// [`DiskScheduler`]: crate::disk::disk_scheduler::DiskScheduler
 #[derive(Debug)]
 pub struct DiskManager {
-    last_allocated_pid: PageId,
-    file: RefCell<std::fs::File>,
+    allocator: Mutex<PageAllocator>,
+    read_only: bool,
+    page_size: usize,
+    file: std::fs::File,
 }
 
-impl DiskManager {
-    /// Creates a new disk manager for the given database file `filename`, e.g. `example.db`.
-    pub(crate) fn new(filename: &str) -> Result<Self> {
-        let path = Path::new(DATA_DIR).join(filename);
+/// Configures how a [`DiskManager`] opens its database file.
+///
+/// By default the file at `path` is opened for reading and writing, and created if it does not
+/// exist yet. A read-only disk manager never creates or truncates the file, and rejects every
+/// operation that would modify it.
+#[derive(Debug, Clone)]
+pub struct DiskManagerBuilder {
+    path: PathBuf,
+    create: bool,
+    truncate: bool,
+    read_only: bool,
+    page_size: Option<usize>,
+}
+
+impl DiskManagerBuilder {
+    pub(crate) fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            create: true,
+            truncate: false,
+            read_only: false,
+            page_size: None,
+        }
+    }
+
+    /// Whether to create the file if it does not exist.
+    pub(crate) fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Whether to discard the contents of an existing file.
+    pub(crate) fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Whether to open the file for inspection only.
+    pub(crate) fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// The page size of a new file, [`DEFAULT_PAGE_SIZE`] by default. An existing file keeps
+    /// the page size recorded in its header, and fails to open if it differs from the one set
+    /// here.
+    pub(crate) fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Opens the database file. An existing file keeps its contents: the header on page 0 is
+    /// validated, the last allocated page id is restored from the file length and the free page
+    /// list is reloaded.
+    pub(crate) fn open(self) -> Result<DiskManager> {
+        if self.read_only && self.truncate {
+            return errinput!("Cannot truncate {} in read-only mode.", self.path.display());
+        }
+        if let Some(page_size) = self.page_size {
+            validate_page_size(page_size)?;
+        }
+
         let file = std::fs::OpenOptions::new()
-            .write(true)
             .read(true)
-            .create(true)
-            .truncate(true)
-            .open(&path)
-            .expect(format!("Unable to create or open file {}.", path.display()).as_str());
+            .write(!self.read_only)
+            .create(self.create && !self.read_only)
+            .truncate(self.truncate)
+            .open(&self.path)
+            .map_err(|e| {
+                Error::IO(format!(
+                    "Unable to open file {}: {}",
+                    self.path.display(),
+                    e
+                ))
+            })?;
 
-        let mut disk_manager = Self {
-            last_allocated_pid: 0,
-            file: RefCell::new(file),
+        let file_len = file.metadata()?.len() as usize;
+        if file_len == 0 {
+            if self.read_only {
+                return errdata!("File {} is empty.", self.path.display());
+            }
+            // A freshly created or truncated file, lay down the header page.
+            let disk_manager = DiskManager {
+                allocator: Mutex::new(PageAllocator::default()),
+                read_only: self.read_only,
+                page_size: self.page_size.unwrap_or(DEFAULT_PAGE_SIZE),
+                file,
+            };
+            disk_manager.write_header(&PageAllocator::default())?;
+            return Ok(disk_manager);
+        }
+
+        // The page size is only known once the header is read, so the header fields are read
+        // on their own first. Check the magic bytes before the checksum, so that foreign files
+        // are reported as such rather than as corrupted databases.
+        if file_len < FILE_HEADER_SIZE {
+            return errdata!(
+                "File {} is too short to be a database file.",
+                self.path.display()
+            );
+        }
+        let mut bytes = [0; FILE_HEADER_SIZE];
+        file.read_exact_at(&mut bytes, 0)?;
+        let header: FileHeader = bytemuck::pod_read_unaligned(&bytes);
+        header.validate(self.page_size)?;
+
+        let page_size = header.page_size as usize;
+        if !file_len.is_multiple_of(page_size) {
+            return errdata!(
+                "File {} has length {} which is not a multiple of the page size {}.",
+                self.path.display(),
+                file_len,
+                page_size
+            );
+        }
+
+        let disk_manager = DiskManager {
+            allocator: Mutex::new(PageAllocator::default()),
+            read_only: self.read_only,
+            page_size,
+            file,
         };
+        let bytes = disk_manager.read_raw_page(&HEADER_PAGE_ID)?;
+        disk_manager.verify_checksum(&HEADER_PAGE_ID, &bytes)?;
 
-        // Initialize the first page, potentially clearing out any garbage data.
-        disk_manager.write(&0, EMPTY_BUFFER)?;
+        {
+            let mut allocator = disk_manager.allocator.lock()?;
+            allocator.last_allocated_pid = file_len / page_size - 1;
+            disk_manager.load_free_list(&mut allocator, header.free_list_head as PageId)?;
+        }
 
         Ok(disk_manager)
     }
+}
+
+impl DiskManager {
+    /// Returns a builder for a disk manager backed by the database file at `path`.
+    pub(crate) fn builder(path: impl AsRef<Path>) -> DiskManagerBuilder {
+        DiskManagerBuilder::new(path)
+    }
 
-    pub fn allocate_page(&mut self) -> Result<PageId> {
-        self.last_allocated_pid += 1;
-        let page_id = self.last_allocated_pid;
+    /// Creates a new disk manager for the given database file `filename` in the default data
+    /// directory, e.g. `example.db`. Any existing file with the same name is truncated.
+    pub(crate) fn new(filename: &str) -> Result<Self> {
+        Self::builder(Path::new(DATA_DIR).join(filename))
+            .truncate(true)
+            .open()
+    }
 
-        self.write(&page_id, EMPTY_BUFFER)?;
-        Ok(page_id)
+    /// Opens the database file `filename` in the default data directory, creating it if it
+    /// does not exist yet.
+    pub(crate) fn open(filename: &str) -> Result<Self> {
+        Self::builder(Path::new(DATA_DIR).join(filename)).open()
+    }
+
+    pub(crate) fn is_read_only(&self) -> bool {
+        self.read_only
     }
 
-    pub fn deallocate_page(&mut self, page_id: &PageId) -> Result<()> {
-        let mut file = self.file.borrow_mut();
-        file.seek(SeekFrom::Start(Self::calculate_offset(page_id)?))?;
-        file.write_all(DELETED_FLAG)?;
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return errinput!("Disk manager is read-only.");
+        }
+        Ok(())
+    }
+
+    /// Walks the on-disk free page list starting at `head`.
+    fn load_free_list(&self, allocator: &mut PageAllocator, head: PageId) -> Result<()> {
+        let mut free_list = Vec::new();
+        let mut page_id = head;
+        while page_id != INVALID_PAGE_ID {
+            if page_id == HEADER_PAGE_ID
+                || page_id > allocator.last_allocated_pid
+                || !allocator.free_pages.insert(page_id)
+            {
+                return errdata!("Corrupted free page list at page {}.", page_id);
+            }
+            free_list.push(page_id);
+            let free_page: FreePageHeader = self.read_struct(&page_id)?;
+            page_id = free_page.next_free_page_id as PageId;
+        }
+
+        // Pages are reused from the end of the vector, so the head goes last.
+        free_list.reverse();
+        allocator.free_list = free_list;
         Ok(())
     }
 
-    fn is_deleted(reader: &mut RefMut<std::fs::File>) -> Result<bool> {
-        let mut buf = [0; 1];
-        let current_offset = reader.stream_position()?;
-        reader.read_exact(&mut buf)?;
-        reader.seek(SeekFrom::Start(current_offset))?;
-        Ok(buf == DELETED_FLAG)
+    fn write_header(&self, allocator: &PageAllocator) -> Result<()> {
+        let header = FileHeader::new(self.page_size, allocator.free_list_head());
+        self.write(&HEADER_PAGE_ID, bytemuck::bytes_of(&header))
+    }
+
+    /// Reads a plain struct stored at the start of the given page.
+    fn read_struct<T: Pod>(&self, page_id: &PageId) -> Result<T> {
+        let bytes = self.read_page(page_id)?;
+        Ok(bytemuck::pod_read_unaligned(
+            &bytes[..std::mem::size_of::<T>()],
+        ))
+    }
+
+    pub(crate) fn last_allocated_page_id(&self) -> PageId {
+        self.allocator.lock().unwrap().last_allocated_pid
+    }
+
+    /// Reads the page and verifies its checksum.
+    fn read_page(&self, page_id: &PageId) -> Result<Bytes> {
+        let bytes = self.read_raw_page(page_id)?;
+        self.verify_checksum(page_id, &bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_raw_page(&self, page_id: &PageId) -> Result<Bytes> {
+        let mut bytes = BytesMut::zeroed(self.page_size);
+        self.file
+            .read_exact_at(&mut bytes, self.calculate_offset(page_id)?)?;
+        Ok(bytes.freeze())
+    }
+
+    fn checksum_offset(&self) -> usize {
+        self.page_size - PAGE_CHECKSUM_SIZE
+    }
+
+    /// Computes the checksum of a page, excluding the checksum trailer itself.
+    fn checksum(&self, page: &[u8]) -> u32 {
+        crc32c::crc32c(&page[..self.checksum_offset()])
+    }
+
+    fn verify_checksum(&self, page_id: &PageId, page: &[u8]) -> Result<()> {
+        let stored = u32::from_le_bytes(page[self.checksum_offset()..].try_into()?);
+        if stored != self.checksum(page) {
+            return Err(Error::Corruption { page_id: *page_id });
+        }
+        Ok(())
+    }
+
+    fn calculate_offset(&self, page_id: &PageId) -> Result<u64> {
+        match (page_id).checked_mul(self.page_size) {
+            Some(value) => Ok(value as u64),
+            None => Err(Error::ArithmeticOverflow),
+        }
+    }
+}
+
+impl PageStore for DiskManager {
+    /// Allocates a zeroed page, reusing a previously deallocated page if there is one and
+    /// growing the file otherwise.
+    fn allocate_page(&self) -> Result<PageId> {
+        self.check_writable()?;
+
+        let mut allocator = self.allocator.lock()?;
+        let page_id = match allocator.free_list.pop() {
+            Some(page_id) => {
+                allocator.free_pages.remove(&page_id);
+                // Unlink the page from the on-disk list before handing it out.
+                self.write_header(&allocator)?;
+                page_id
+            }
+            None => {
+                allocator.last_allocated_pid += 1;
+                allocator.last_allocated_pid
+            }
+        };
+
+        self.write(&page_id, &[])?;
+        Ok(page_id)
     }
 
-    pub(crate) fn read(&mut self, page_id: &PageId) -> Result<Option<Bytes>> {
-        let mut file = self.file.borrow_mut();
-        file.seek(SeekFrom::Start(Self::calculate_offset(page_id)?))?;
+    /// Returns the page to the free page list so a later `allocate_page` can reuse it.
+    fn deallocate_page(&self, page_id: &PageId) -> Result<()> {
+        self.check_writable()?;
+
+        let mut allocator = self.allocator.lock()?;
+        if *page_id == HEADER_PAGE_ID || *page_id > allocator.last_allocated_pid {
+            return errinput!(
+                "Cannot deallocate page {} which was never allocated.",
+                page_id
+            );
+        }
+        if allocator.free_pages.contains(page_id) {
+            return errinput!("Page {} is already deallocated.", page_id);
+        }
 
-        if Self::is_deleted(&mut file)? {
+        let free_page = FreePageHeader {
+            next_free_page_id: allocator.free_list_head() as u64,
+        };
+        self.write(page_id, bytemuck::bytes_of(&free_page))?;
+
+        allocator.free_list.push(*page_id);
+        allocator.free_pages.insert(*page_id);
+        self.write_header(&allocator)
+    }
+
+    /// Reads the page with the given id. Returns `None` if the page has been deallocated.
+    fn read(&self, page_id: &PageId) -> Result<Option<Bytes>> {
+        if self.allocator.lock()?.free_pages.contains(page_id) {
             return Ok(None);
         }
 
-        let bytes = {
-            let mut bytes = BytesMut::zeroed(PAGE_SIZE_BYTES);
-            file.read_exact(&mut bytes)?;
-            bytes.freeze()
+        self.read_page(page_id).map(Some)
+    }
+
+    /// Writes `data` to the start of the page, zero-filling the rest of it. The trailing
+    /// `PAGE_CHECKSUM_SIZE` bytes hold the page checksum, so `data` must fit in the
+    /// `page_data_size` bytes before them.
+    fn write(&self, page_id: &PageId, data: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        if data.len() > self.checksum_offset() {
+            return errdata!(
+                "Page data of {} bytes does not fit in {} bytes.",
+                data.len(),
+                self.checksum_offset()
+            );
+        }
+
+        let mut buf = vec![0; self.page_size];
+        buf[..data.len()].copy_from_slice(data);
+        let checksum = self.checksum(&buf);
+        let checksum_offset = self.checksum_offset();
+        buf[checksum_offset..].copy_from_slice(&checksum.to_le_bytes());
+
+        self.file
+            .write_all_at(&buf, self.calculate_offset(page_id)?)?;
+
+        Ok(())
+    }
+
+    /// Flushes all completed writes to stable storage.
+    fn sync(&self) -> Result<()> {
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::page_data_size;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::sync::Arc;
+
+    fn temp_db_path(dir: &tempfile::TempDir) -> String {
+        dir.path().join("test.db").to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_open_restores_pages() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = temp_db_path(&dir);
+
+        let page_id = {
+            let disk = DiskManager::open(&path)?;
+            assert_eq!(0, disk.last_allocated_page_id());
+            let page_id = disk.allocate_page()?;
+            disk.allocate_page()?;
+            disk.write(&page_id, &[7; 16])?;
+            page_id
         };
 
-        Ok(Some(bytes))
+        let disk = DiskManager::open(&path)?;
+        assert_eq!(2, disk.last_allocated_page_id());
+        let data = disk.read(&page_id)?.unwrap();
+        assert_eq!(&[7; 16], &data[..16]);
+
+        // Newly allocated pages continue after the restored ones.
+        assert_eq!(3, disk.allocate_page()?);
+
+        Ok(())
     }
 
-    pub(crate) fn write(&mut self, page_id: &PageId, data: &[u8]) -> Result<()> {
-        if data.len() > PAGE_SIZE_BYTES {
-            return errdata!("Page data must fit in a page.");
+    #[test]
+    fn test_new_truncates_existing_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = temp_db_path(&dir);
+
+        {
+            let disk = DiskManager::open(&path)?;
+            disk.allocate_page()?;
         }
 
-        let mut file = self.file.borrow_mut();
-        file.seek(SeekFrom::Start(Self::calculate_offset(page_id)?))?;
+        DiskManager::new(&path)?;
+        let disk = DiskManager::open(&path)?;
+        assert_eq!(0, disk.last_allocated_page_id());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deallocated_pages_are_reused() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let disk = DiskManager::open(&temp_db_path(&dir))?;
+
+        let page1 = disk.allocate_page()?;
+        let page2 = disk.allocate_page()?;
+        let page3 = disk.allocate_page()?;
+        disk.write(&page2, &[9; 32])?;
+
+        disk.deallocate_page(&page2)?;
+        disk.deallocate_page(&page1)?;
+        assert_eq!(None, disk.read(&page1)?);
+        assert_eq!(None, disk.read(&page2)?);
+        assert!(disk.read(&page3)?.is_some());
+
+        // Freed pages are handed out most recently freed first and come back zeroed.
+        assert_eq!(page1, disk.allocate_page()?);
+        assert_eq!(page2, disk.allocate_page()?);
+        assert_eq!(&[0; 32], &disk.read(&page2)?.unwrap()[..32]);
+
+        // Once the free list is exhausted the file grows again.
+        assert_eq!(page3 + 1, disk.allocate_page()?);
+        assert_eq!(page3 + 1, disk.last_allocated_page_id());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_free_list_survives_reopen() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = temp_db_path(&dir);
+
+        {
+            let disk = DiskManager::open(&path)?;
+            for _ in 0..4 {
+                disk.allocate_page()?;
+            }
+            disk.deallocate_page(&2)?;
+            disk.deallocate_page(&4)?;
+            disk.sync()?;
+        }
+
+        let disk = DiskManager::open(&path)?;
+        assert_eq!(None, disk.read(&2)?);
+        assert_eq!(None, disk.read(&4)?);
+        assert_eq!(4, disk.allocate_page()?);
+        assert_eq!(2, disk.allocate_page()?);
+        assert_eq!(5, disk.allocate_page()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_page_starting_with_one() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let disk = DiskManager::open(&temp_db_path(&dir))?;
+
+        let page_id = disk.allocate_page()?;
+        disk.write(&page_id, &[1, 2, 3])?;
+
+        assert_eq!(&[1, 2, 3], &disk.read(&page_id)?.unwrap()[..3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deallocate_invalid_page() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let disk = DiskManager::open(&temp_db_path(&dir))?;
+
+        let page_id = disk.allocate_page()?;
+        disk.deallocate_page(&page_id)?;
+
+        assert!(matches!(
+            disk.deallocate_page(&page_id),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            disk.deallocate_page(&HEADER_PAGE_ID),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            disk.deallocate_page(&(page_id + 1)),
+            Err(Error::InvalidInput(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_opens_arbitrary_path() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("nested").join("db");
+        std::fs::create_dir(dir.path().join("nested"))?;
+
+        let disk = DiskManager::builder(&path).open()?;
+        let page_id = disk.allocate_page()?;
+        disk.write(&page_id, &[4; 8])?;
+        drop(disk);
+
+        let disk = DiskManager::builder(&path).create(false).open()?;
+        assert_eq!(&[4; 8], &disk.read(&page_id)?.unwrap()[..8]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_failures_return_io_error() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let missing = dir.path().join("missing.db");
+        assert!(matches!(
+            DiskManager::builder(&missing).create(false).open(),
+            Err(Error::IO(_))
+        ));
+        assert!(!missing.exists());
+
+        let no_such_dir = dir.path().join("no_such_dir").join("test.db");
+        assert!(matches!(
+            DiskManager::builder(&no_such_dir).open(),
+            Err(Error::IO(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("test.db");
+
+        let page_id = {
+            let disk = DiskManager::builder(&path).open()?;
+            let page_id = disk.allocate_page()?;
+            disk.write(&page_id, &[5; 8])?;
+            page_id
+        };
+
+        let disk = DiskManager::builder(&path).read_only(true).open()?;
+        assert!(disk.is_read_only());
+        assert_eq!(&[5; 8], &disk.read(&page_id)?.unwrap()[..8]);
+
+        assert!(matches!(disk.allocate_page(), Err(Error::InvalidInput(_))));
+        assert!(matches!(
+            disk.deallocate_page(&page_id),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            disk.write(&page_id, &[6; 8]),
+            Err(Error::InvalidInput(_))
+        ));
+        assert_eq!(&[5; 8], &disk.read(&page_id)?.unwrap()[..8]);
+
+        // A read-only disk manager never creates or truncates files.
+        let missing = dir.path().join("missing.db");
+        assert!(matches!(
+            DiskManager::builder(&missing).read_only(true).open(),
+            Err(Error::IO(_))
+        ));
+        assert!(matches!(
+            DiskManager::builder(&path)
+                .read_only(true)
+                .truncate(true)
+                .open(),
+            Err(Error::InvalidInput(_))
+        ));
+
+        Ok(())
+    }
+
+    /// Overwrites `len` bytes at `offset` of the given page directly in the file.
+    fn overwrite_file(path: &str, page_id: PageId, offset: usize, data: &[u8]) -> Result<()> {
+        let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+        file.seek(SeekFrom::Start(
+            (page_id * DEFAULT_PAGE_SIZE + offset) as u64,
+        ))?;
         file.write_all(data)?;
-        file.sync_all()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_detects_bit_flip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = temp_db_path(&dir);
+
+        let disk = DiskManager::open(&path)?;
+        let page1 = disk.allocate_page()?;
+        let page2 = disk.allocate_page()?;
+        disk.write(&page1, &[3; 64])?;
+        disk.write(&page2, &[3; 64])?;
+
+        overwrite_file(&path, page1, 10, &[2])?;
+
+        assert_eq!(Err(Error::Corruption { page_id: page1 }), disk.read(&page1));
+        assert_eq!(&[3; 64], &disk.read(&page2)?.unwrap()[..64]);
+
+        // Rewriting the page stamps a fresh checksum.
+        disk.write(&page1, &[4; 64])?;
+        assert_eq!(&[4; 64], &disk.read(&page1)?.unwrap()[..64]);
 
         Ok(())
     }
 
-    fn calculate_offset(page_id: &PageId) -> Result<u64> {
-        match (page_id).checked_mul(PAGE_SIZE_BYTES) {
-            Some(value) => Ok(value as u64),
-            None => Err(Error::ArithmeticOverflow),
+    #[test]
+    fn test_checksum_detects_torn_write() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = temp_db_path(&dir);
+
+        let disk = DiskManager::open(&path)?;
+        let page_id = disk.allocate_page()?;
+        disk.write(&page_id, &[1; page_data_size(DEFAULT_PAGE_SIZE)])?;
+
+        // Only the first half of a newer version of the page made it to disk.
+        overwrite_file(&path, page_id, 0, &[2; DEFAULT_PAGE_SIZE / 2])?;
+
+        assert_eq!(Err(Error::Corruption { page_id }), disk.read(&page_id));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_rejects_data_over_the_checksum() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let disk = DiskManager::open(&temp_db_path(&dir))?;
+        let page_id = disk.allocate_page()?;
+
+        assert!(disk.write(&page_id, &[1; DEFAULT_PAGE_SIZE]).is_err());
+        disk.write(&page_id, &[2; page_data_size(DEFAULT_PAGE_SIZE)])?;
+        assert_eq!(
+            &[2; page_data_size(DEFAULT_PAGE_SIZE)],
+            &disk.read(&page_id)?.unwrap()[..page_data_size(DEFAULT_PAGE_SIZE)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_detects_corrupted_header() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = temp_db_path(&dir);
+
+        DiskManager::open(&path)?;
+        overwrite_file(&path, HEADER_PAGE_ID, FILE_HEADER_SIZE, &[1])?;
+
+        assert_eq!(
+            Err(Error::Corruption {
+                page_id: HEADER_PAGE_ID
+            }),
+            DiskManager::open(&path).map(|_| ())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_reads_and_writes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let disk = Arc::new(DiskManager::open(&temp_db_path(&dir))?);
+
+        let page_ids = (0..8)
+            .map(|_| disk.allocate_page())
+            .collect::<Result<Vec<_>>>()?;
+
+        let threads: Vec<_> = page_ids
+            .iter()
+            .map(|&page_id| {
+                let disk = disk.clone();
+                std::thread::spawn(move || -> Result<()> {
+                    for round in 0..32u8 {
+                        let data = [page_id as u8, round];
+                        disk.write(&page_id, &data)?;
+                        assert_eq!(&data, &disk.read(&page_id)?.unwrap()[..2]);
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap()?;
+        }
+
+        for page_id in page_ids {
+            assert_eq!(&[page_id as u8, 31], &disk.read(&page_id)?.unwrap()[..2]);
         }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_rejects_invalid_header() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = temp_db_path(&dir);
+        std::fs::write(&path, [0xAB; DEFAULT_PAGE_SIZE])?;
+
+        assert!(matches!(
+            DiskManager::open(&path),
+            Err(Error::InvalidData(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_rejects_partial_page() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = temp_db_path(&dir);
+        std::fs::write(&path, [0; DEFAULT_PAGE_SIZE + 1])?;
+
+        assert!(matches!(
+            DiskManager::open(&path),
+            Err(Error::InvalidData(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_size_is_recorded_in_header() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("test.db");
+
+        let page_id = {
+            let disk = DiskManager::builder(&path).page_size(16384).open()?;
+            let page_id = disk.allocate_page()?;
+            disk.write(&page_id, &[8; 16000])?;
+            page_id
+        };
+
+        let disk = DiskManager::builder(&path).open()?;
+        assert_eq!(16384, disk.page_size());
+        assert_eq!(16384, disk.read(&page_id)?.unwrap().len());
+        assert_eq!(&[8; 16000], &disk.read(&page_id)?.unwrap()[..16000]);
+        drop(disk);
+
+        assert!(matches!(
+            DiskManager::builder(&path).page_size(4096).open(),
+            Err(Error::InvalidData(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_page_size() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("test.db");
+
+        for page_size in [0, 2048, 5000, 131072] {
+            assert!(matches!(
+                DiskManager::builder(&path).page_size(page_size).open(),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+        assert!(!path.exists());
+
+        Ok(())
     }
 }
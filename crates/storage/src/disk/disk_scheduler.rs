@@ -0,0 +1,288 @@
+use crate::disk::page_store::PageStore;
+use crate::typedef::PageId;
+use crate::Result;
+use bytes::Bytes;
+use rustdb_error::Error;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// A request for the background worker of the [`DiskScheduler`]. The result of the request is
+/// sent back through `callback` once the worker has executed it.
+pub(crate) enum DiskRequest {
+    Read {
+        page_id: PageId,
+        callback: Sender<Result<Option<Bytes>>>,
+    },
+    Write {
+        page_id: PageId,
+        data: Bytes,
+        callback: Sender<Result<()>>,
+    },
+    /// Makes every write completed before this request durable. Writes still queued or in
+    /// flight on another worker are not covered, so wait for them before scheduling a sync.
+    Sync { callback: Sender<Result<()>> },
+}
+
+/// Number of worker threads used by [`DiskScheduler::new`].
+pub(crate) const DEFAULT_WORKER_COUNT: usize = 4;
+
+/// Schedules page reads and writes on background worker threads, so that callers only block
+/// when they wait on the returned completion channel.
+///
+/// Each worker drains every request queued up since its last round as a single batch. Sync
+/// requests within a batch are executed once after all reads and writes of the batch, so that
+/// flushing many dirty pages costs a single `fsync`. Batches taken by different workers run
+/// concurrently, so requests that depend on each other must wait for one another's completion.
+pub(crate) struct DiskScheduler {
+    page_store: Arc<dyn PageStore>,
+    /// `None` once the scheduler is shutting down.
+    request_queue: Option<Sender<DiskRequest>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl DiskScheduler {
+    pub(crate) fn new(page_store: Arc<dyn PageStore>) -> Self {
+        Self::with_workers(page_store, DEFAULT_WORKER_COUNT)
+    }
+
+    pub(crate) fn with_workers(page_store: Arc<dyn PageStore>, worker_count: usize) -> Self {
+        assert!(worker_count > 0, "Disk scheduler needs at least one worker");
+
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..worker_count)
+            .map(|i| {
+                let page_store = page_store.clone();
+                let receiver = receiver.clone();
+                std::thread::Builder::new()
+                    .name(format!("disk-scheduler-{}", i))
+                    .spawn(move || Self::run_worker(page_store, receiver))
+                    .expect("Failed to spawn disk scheduler worker")
+            })
+            .collect();
+
+        Self {
+            page_store,
+            request_queue: Some(sender),
+            workers,
+        }
+    }
+
+    /// Queues a request for the background worker.
+    pub(crate) fn schedule(&self, request: DiskRequest) {
+        let queue = self
+            .request_queue
+            .as_ref()
+            .expect("Disk scheduler is shut down");
+        // The workers only exit once the queue is closed, which happens on drop.
+        queue
+            .send(request)
+            .expect("Disk scheduler worker exited unexpectedly");
+    }
+
+    pub(crate) fn schedule_read(&self, page_id: PageId) -> Receiver<Result<Option<Bytes>>> {
+        let (callback, receiver) = mpsc::channel();
+        self.schedule(DiskRequest::Read { page_id, callback });
+        receiver
+    }
+
+    pub(crate) fn schedule_write(&self, page_id: PageId, data: Bytes) -> Receiver<Result<()>> {
+        let (callback, receiver) = mpsc::channel();
+        self.schedule(DiskRequest::Write {
+            page_id,
+            data,
+            callback,
+        });
+        receiver
+    }
+
+    pub(crate) fn schedule_sync(&self) -> Receiver<Result<()>> {
+        let (callback, receiver) = mpsc::channel();
+        self.schedule(DiskRequest::Sync { callback });
+        receiver
+    }
+
+    /// Blocks until the request behind `receiver` completes and returns its result.
+    pub(crate) fn wait<T>(receiver: Receiver<Result<T>>) -> Result<T> {
+        receiver
+            .recv()
+            .map_err(|_| Error::IO("Disk request was dropped before completion".to_string()))?
+    }
+
+    /// Returns the result of the request behind `receiver` if it has completed, without
+    /// blocking.
+    pub(crate) fn try_wait<T>(receiver: &Receiver<Result<T>>) -> Option<Result<T>> {
+        match receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(Error::IO(
+                "Disk request was dropped before completion".to_string(),
+            ))),
+        }
+    }
+
+    pub(crate) fn allocate_page(&self) -> Result<PageId> {
+        self.page_store.allocate_page()
+    }
+
+    pub(crate) fn deallocate_page(&self, page_id: &PageId) -> Result<()> {
+        self.page_store.deallocate_page(page_id)
+    }
+
+    fn run_worker(store: Arc<dyn PageStore>, receiver: Arc<Mutex<Receiver<DiskRequest>>>) {
+        loop {
+            let batch: Vec<DiskRequest> = {
+                let receiver = receiver.lock().unwrap();
+                // `recv` fails once every sender is dropped, i.e. the scheduler is shutting down.
+                let Ok(first) = receiver.recv() else {
+                    return;
+                };
+                std::iter::once(first).chain(receiver.try_iter()).collect()
+            };
+
+            let mut sync_callbacks = Vec::new();
+
+            for request in batch {
+                // A send only fails if the requester stopped waiting, which is fine to ignore.
+                match request {
+                    DiskRequest::Read { page_id, callback } => {
+                        let _ = callback.send(store.read(&page_id));
+                    }
+                    DiskRequest::Write {
+                        page_id,
+                        data,
+                        callback,
+                    } => {
+                        let _ = callback.send(store.write(&page_id, &data));
+                    }
+                    DiskRequest::Sync { callback } => sync_callbacks.push(callback),
+                }
+            }
+
+            if !sync_callbacks.is_empty() {
+                let result = store.sync();
+                for callback in sync_callbacks {
+                    let _ = callback.send(result.clone());
+                }
+            }
+        }
+    }
+}
+
+impl Drop for DiskScheduler {
+    /// Closes the request queue and waits for the workers to finish the queued requests.
+    fn drop(&mut self) {
+        self.request_queue.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::disk_manager::DiskManager;
+
+    fn setup() -> Result<(tempfile::TempDir, DiskScheduler)> {
+        let dir = tempfile::tempdir()?;
+        let disk = DiskManager::builder(dir.path().join("test.db")).open()?;
+        Ok((dir, DiskScheduler::new(Arc::new(disk))))
+    }
+
+    #[test]
+    fn test_schedule_write_then_read() -> Result<()> {
+        let (_dir, scheduler) = setup()?;
+        let page_id = scheduler.allocate_page()?;
+
+        let write = scheduler.schedule_write(page_id, Bytes::from_static(&[1, 2, 3]));
+        DiskScheduler::wait(write)?;
+
+        let read = scheduler.schedule_read(page_id);
+        let data = DiskScheduler::wait(read)?.unwrap();
+        assert_eq!(&[1, 2, 3], &data[..3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batched_writes_and_syncs() -> Result<()> {
+        let (_dir, scheduler) = setup()?;
+        let page_ids = (0..16)
+            .map(|_| scheduler.allocate_page())
+            .collect::<Result<Vec<_>>>()?;
+
+        let writes: Vec<_> = page_ids
+            .iter()
+            .map(|&page_id| scheduler.schedule_write(page_id, Bytes::from(vec![page_id as u8; 8])))
+            .collect();
+        for write in writes {
+            DiskScheduler::wait(write)?;
+        }
+
+        let syncs: Vec<_> = (0..4).map(|_| scheduler.schedule_sync()).collect();
+        for sync in syncs {
+            DiskScheduler::wait(sync)?;
+        }
+
+        for page_id in page_ids {
+            let data = DiskScheduler::wait(scheduler.schedule_read(page_id))?.unwrap();
+            assert_eq!(&[page_id as u8; 8], &data[..8]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_reads() -> Result<()> {
+        let (_dir, scheduler) = setup()?;
+        let page_ids = (0..32)
+            .map(|_| scheduler.allocate_page())
+            .collect::<Result<Vec<_>>>()?;
+        for &page_id in &page_ids {
+            let data = Bytes::from(vec![page_id as u8; 4]);
+            DiskScheduler::wait(scheduler.schedule_write(page_id, data))?;
+        }
+
+        let reads: Vec<_> = page_ids
+            .iter()
+            .map(|&page_id| scheduler.schedule_read(page_id))
+            .collect();
+        for (page_id, read) in page_ids.into_iter().zip(reads) {
+            let data = DiskScheduler::wait(read)?.unwrap();
+            assert_eq!(&[page_id as u8; 4], &data[..4]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors_are_reported_through_callback() -> Result<()> {
+        let (_dir, scheduler) = setup()?;
+
+        let write = scheduler.schedule_write(1, Bytes::from(vec![0; 8192]));
+        assert!(matches!(
+            DiskScheduler::wait(write),
+            Err(Error::InvalidData(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_completes_queued_requests() -> Result<()> {
+        let (_dir, scheduler) = setup()?;
+        let page_id = scheduler.allocate_page()?;
+
+        let write = scheduler.schedule_write(page_id, Bytes::from_static(&[7]));
+        let sync = scheduler.schedule_sync();
+        drop(scheduler);
+
+        DiskScheduler::wait(write)?;
+        DiskScheduler::wait(sync)?;
+
+        Ok(())
+    }
+}
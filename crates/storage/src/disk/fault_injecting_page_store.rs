@@ -0,0 +1,277 @@
+use crate::disk::page_store::PageStore;
+use crate::typedef::PageId;
+use crate::Result;
+use bytes::Bytes;
+use rustdb_error::{errdata, Error};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum WriteFault {
+    /// The write fails without touching the page.
+    #[default]
+    Fail,
+    /// Only the first half of the page is written, but the write reports success.
+    Tear,
+}
+
+#[derive(Debug, Default)]
+struct FaultState {
+    /// The number of writes left until `write_fault` triggers.
+    writes_until_fault: Option<usize>,
+    write_fault: WriteFault,
+    /// The number of reads left until a read fails.
+    reads_until_fault: Option<usize>,
+    /// Pages written since the last sync. They only reach the inner store on sync, and are
+    /// lost on a crash.
+    unsynced: HashMap<PageId, Bytes>,
+    crashed: bool,
+}
+
+impl FaultState {
+    fn check_crashed(&self) -> Result<()> {
+        if self.crashed {
+            return Err(Error::IO("Page store has crashed.".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Counts down a scripted fault. Returns true, and disarms the fault, once it triggers.
+fn countdown(remaining: &mut Option<usize>) -> bool {
+    match remaining {
+        Some(1) => {
+            *remaining = None;
+            true
+        }
+        Some(n) => {
+            *n -= 1;
+            false
+        }
+        None => false,
+    }
+}
+
+/// A page store wrapper for testing failure handling. It can be scripted to fail the Nth read
+/// or write, to tear a page write, or to crash.
+///
+/// Writes are buffered like in an OS page cache and only reach the inner store on `sync`, so a
+/// [`crash`](Self::crash) loses every write since the last sync. Allocations and deallocations
+/// go straight to the inner store, which makes them durable immediately.
+pub struct FaultInjectingPageStore {
+    inner: Arc<dyn PageStore>,
+    state: Mutex<FaultState>,
+}
+
+impl FaultInjectingPageStore {
+    pub(crate) fn new(inner: Arc<dyn PageStore>) -> Self {
+        Self {
+            inner,
+            state: Mutex::new(FaultState::default()),
+        }
+    }
+
+    /// Makes the `n`th write from now on fail with an IO error, counting from 1.
+    pub(crate) fn fail_nth_write(&self, n: usize) {
+        assert!(n > 0, "Writes are counted from 1");
+        let mut state = self.state.lock().unwrap();
+        state.writes_until_fault = Some(n);
+        state.write_fault = WriteFault::Fail;
+    }
+
+    /// Makes the `n`th write from now on tear, counting from 1. Only the first half of the page
+    /// is written and the second half keeps its previous contents, while the write reports
+    /// success.
+    pub(crate) fn tear_nth_write(&self, n: usize) {
+        assert!(n > 0, "Writes are counted from 1");
+        let mut state = self.state.lock().unwrap();
+        state.writes_until_fault = Some(n);
+        state.write_fault = WriteFault::Tear;
+    }
+
+    /// Makes the `n`th read from now on fail with an IO error, counting from 1.
+    pub(crate) fn fail_nth_read(&self, n: usize) {
+        assert!(n > 0, "Reads are counted from 1");
+        self.state.lock().unwrap().reads_until_fault = Some(n);
+    }
+
+    /// Simulates a crash: every write since the last sync is dropped, and every further
+    /// operation fails with an IO error. The inner store keeps the state as of the last sync.
+    pub(crate) fn crash(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.unsynced.clear();
+        state.crashed = true;
+    }
+
+    /// Returns the wrapped store, e.g. to inspect what survived a crash.
+    pub(crate) fn inner(&self) -> &Arc<dyn PageStore> {
+        &self.inner
+    }
+
+    fn current_page(&self, state: &FaultState, page_id: &PageId) -> Result<Bytes> {
+        match state.unsynced.get(page_id) {
+            Some(data) => Ok(data.clone()),
+            None => Ok(self
+                .inner
+                .read(page_id)?
+                .unwrap_or_else(|| Bytes::from(vec![0; self.page_size()]))),
+        }
+    }
+}
+
+impl PageStore for FaultInjectingPageStore {
+    fn allocate_page(&self) -> Result<PageId> {
+        let mut state = self.state.lock()?;
+        state.check_crashed()?;
+        let page_id = self.inner.allocate_page()?;
+        state.unsynced.remove(&page_id);
+        Ok(page_id)
+    }
+
+    fn deallocate_page(&self, page_id: &PageId) -> Result<()> {
+        let mut state = self.state.lock()?;
+        state.check_crashed()?;
+        self.inner.deallocate_page(page_id)?;
+        state.unsynced.remove(page_id);
+        Ok(())
+    }
+
+    fn read(&self, page_id: &PageId) -> Result<Option<Bytes>> {
+        let mut state = self.state.lock()?;
+        state.check_crashed()?;
+        if countdown(&mut state.reads_until_fault) {
+            return Err(Error::IO(format!(
+                "Injected read failure on page {}.",
+                page_id
+            )));
+        }
+        match state.unsynced.get(page_id) {
+            Some(data) => Ok(Some(data.clone())),
+            None => self.inner.read(page_id),
+        }
+    }
+
+    fn write(&self, page_id: &PageId, data: &[u8]) -> Result<()> {
+        let page_size = self.page_size();
+        if data.len() > page_size {
+            return errdata!("Page data must fit in a page.");
+        }
+
+        let mut state = self.state.lock()?;
+        state.check_crashed()?;
+
+        let mut page = vec![0; page_size];
+        page[..data.len()].copy_from_slice(data);
+
+        if countdown(&mut state.writes_until_fault) {
+            match state.write_fault {
+                WriteFault::Fail => {
+                    return Err(Error::IO(format!(
+                        "Injected write failure on page {}.",
+                        page_id
+                    )));
+                }
+                WriteFault::Tear => {
+                    let old = self.current_page(&state, page_id)?;
+                    page[page_size / 2..].copy_from_slice(&old[page_size / 2..]);
+                }
+            }
+        }
+
+        state.unsynced.insert(*page_id, Bytes::from(page));
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        let mut state = self.state.lock()?;
+        state.check_crashed()?;
+        let unsynced: Vec<PageId> = state.unsynced.keys().copied().collect();
+        for page_id in unsynced {
+            // Pages stay buffered until written, so a failed sync can be retried.
+            self.inner.write(&page_id, &state.unsynced[&page_id])?;
+            state.unsynced.remove(&page_id);
+        }
+        self.inner.sync()
+    }
+
+    fn page_size(&self) -> usize {
+        self.inner.page_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::memory_page_store::MemoryPageStore;
+    use crate::page::DEFAULT_PAGE_SIZE;
+
+    fn setup() -> Result<(FaultInjectingPageStore, PageId)> {
+        let store = FaultInjectingPageStore::new(Arc::new(MemoryPageStore::new()));
+        let page_id = store.allocate_page()?;
+        Ok((store, page_id))
+    }
+
+    #[test]
+    fn test_fail_nth_write() -> Result<()> {
+        let (store, page_id) = setup()?;
+        store.fail_nth_write(2);
+
+        store.write(&page_id, &[1])?;
+        assert!(matches!(store.write(&page_id, &[2]), Err(Error::IO(_))));
+        assert_eq!(1, store.read(&page_id)?.unwrap()[0]);
+
+        // The fault only triggers once.
+        store.write(&page_id, &[3])?;
+        assert_eq!(3, store.read(&page_id)?.unwrap()[0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fail_nth_read() -> Result<()> {
+        let (store, page_id) = setup()?;
+        store.fail_nth_read(1);
+
+        assert!(matches!(store.read(&page_id), Err(Error::IO(_))));
+        assert!(store.read(&page_id)?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_torn_write() -> Result<()> {
+        let (store, page_id) = setup()?;
+        store.write(&page_id, &[1; DEFAULT_PAGE_SIZE])?;
+
+        store.tear_nth_write(1);
+        store.write(&page_id, &[2; DEFAULT_PAGE_SIZE])?;
+
+        let data = store.read(&page_id)?.unwrap();
+        assert!(data[..DEFAULT_PAGE_SIZE / 2].iter().all(|&b| b == 2));
+        assert!(data[DEFAULT_PAGE_SIZE / 2..].iter().all(|&b| b == 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_crash_drops_unsynced_writes() -> Result<()> {
+        let (store, page_id) = setup()?;
+        let other_page_id = store.allocate_page()?;
+
+        store.write(&page_id, &[1])?;
+        store.sync()?;
+        store.write(&page_id, &[2])?;
+        store.write(&other_page_id, &[3])?;
+
+        store.crash();
+        assert!(matches!(store.read(&page_id), Err(Error::IO(_))));
+        assert!(matches!(store.write(&page_id, &[4]), Err(Error::IO(_))));
+        assert!(matches!(store.sync(), Err(Error::IO(_))));
+        assert!(matches!(store.allocate_page(), Err(Error::IO(_))));
+
+        assert_eq!(1, store.inner().read(&page_id)?.unwrap()[0]);
+        assert_eq!(0, store.inner().read(&other_page_id)?.unwrap()[0]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use crate::disk::page_store::PageStore;
+use crate::page::{validate_page_size, DEFAULT_PAGE_SIZE};
+use crate::typedef::PageId;
+use crate::Result;
+use bytes::Bytes;
+use rustdb_error::{errdata, errinput, Error};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Default)]
+struct MemoryPages {
+    last_allocated_pid: PageId,
+    /// Allocated pages. Deallocated pages are removed and their ids pushed to `free_list`.
+    pages: HashMap<PageId, Bytes>,
+    free_list: Vec<PageId>,
+}
+
+/// A page store that keeps all pages in memory, for tests and ephemeral databases. Like the
+/// [`DiskManager`], page ids start at 1 and deallocated page ids are reused.
+///
+/// [`DiskManager`]: crate::disk::disk_manager::DiskManager
+#[derive(Debug)]
+pub struct MemoryPageStore {
+    pages: Mutex<MemoryPages>,
+    page_size: usize,
+}
+
+impl Default for MemoryPageStore {
+    fn default() -> Self {
+        Self::with_page_size(DEFAULT_PAGE_SIZE)
+    }
+}
+
+impl MemoryPageStore {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a store of pages of `page_size` bytes. Panics if the page size is invalid.
+    pub(crate) fn with_page_size(page_size: usize) -> Self {
+        validate_page_size(page_size).unwrap();
+        Self {
+            pages: Mutex::new(MemoryPages::default()),
+            page_size,
+        }
+    }
+
+    /// Returns the number of pages currently allocated.
+    pub(crate) fn page_count(&self) -> usize {
+        self.pages.lock().unwrap().pages.len()
+    }
+}
+
+impl PageStore for MemoryPageStore {
+    fn allocate_page(&self) -> Result<PageId> {
+        let mut pages = self.pages.lock()?;
+        let page_id = match pages.free_list.pop() {
+            Some(page_id) => page_id,
+            None => {
+                pages.last_allocated_pid += 1;
+                pages.last_allocated_pid
+            }
+        };
+        pages
+            .pages
+            .insert(page_id, Bytes::from(vec![0; self.page_size]));
+        Ok(page_id)
+    }
+
+    fn deallocate_page(&self, page_id: &PageId) -> Result<()> {
+        let mut pages = self.pages.lock()?;
+        if pages.pages.remove(page_id).is_none() {
+            return errinput!("Page {} is not allocated.", page_id);
+        }
+        pages.free_list.push(*page_id);
+        Ok(())
+    }
+
+    fn read(&self, page_id: &PageId) -> Result<Option<Bytes>> {
+        let pages = self.pages.lock()?;
+        if let Some(data) = pages.pages.get(page_id) {
+            return Ok(Some(data.clone()));
+        }
+        if pages.free_list.contains(page_id) {
+            return Ok(None);
+        }
+        Err(Error::IO(format!("Page {} does not exist.", page_id)))
+    }
+
+    fn write(&self, page_id: &PageId, data: &[u8]) -> Result<()> {
+        if data.len() > self.page_size {
+            return errdata!("Page data must fit in a page.");
+        }
+
+        let mut pages = self.pages.lock()?;
+        let Some(page) = pages.pages.get_mut(page_id) else {
+            return Err(Error::IO(format!("Page {} is not allocated.", page_id)));
+        };
+
+        let mut buf = vec![0; self.page_size];
+        buf[..data.len()].copy_from_slice(data);
+        *page = Bytes::from(buf);
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_write() -> Result<()> {
+        let store = MemoryPageStore::new();
+        let page_id = store.allocate_page()?;
+        assert_eq!(1, page_id);
+        assert_eq!(&[0; DEFAULT_PAGE_SIZE], &store.read(&page_id)?.unwrap()[..]);
+
+        store.write(&page_id, &[1, 2, 3])?;
+        let data = store.read(&page_id)?.unwrap();
+        assert_eq!(&[1, 2, 3], &data[..3]);
+        assert_eq!(DEFAULT_PAGE_SIZE, data.len());
+
+        assert!(matches!(
+            store.write(&page_id, &[0; DEFAULT_PAGE_SIZE + 1]),
+            Err(Error::InvalidData(_))
+        ));
+        assert!(matches!(store.read(&(page_id + 1)), Err(Error::IO(_))));
+        assert!(matches!(
+            store.write(&(page_id + 1), &[1]),
+            Err(Error::IO(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deallocated_pages_are_reused() -> Result<()> {
+        let store = MemoryPageStore::new();
+        let page1 = store.allocate_page()?;
+        let page2 = store.allocate_page()?;
+        store.write(&page1, &[9; 8])?;
+
+        store.deallocate_page(&page1)?;
+        assert_eq!(None, store.read(&page1)?);
+        assert_eq!(1, store.page_count());
+        assert!(matches!(
+            store.deallocate_page(&page1),
+            Err(Error::InvalidInput(_))
+        ));
+
+        assert_eq!(page1, store.allocate_page()?);
+        assert_eq!(&[0; 8], &store.read(&page1)?.unwrap()[..8]);
+        assert_eq!(page2 + 1, store.allocate_page()?);
+
+        Ok(())
+    }
+}
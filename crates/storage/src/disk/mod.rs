@@ -1 +1,5 @@
-pub(crate) mod disk_manager;
\ No newline at end of file
+pub(crate) mod disk_manager;
+pub(crate) mod disk_scheduler;
+pub(crate) mod fault_injecting_page_store;
+pub(crate) mod memory_page_store;
+pub(crate) mod page_store;
@@ -0,0 +1,27 @@
+use crate::typedef::PageId;
+use crate::Result;
+use bytes::Bytes;
+
+/// A backend that stores fixed-size pages, e.g. a database file or memory. The buffer pool
+/// reads and writes pages through a shared `Arc<dyn PageStore>`, so implementations must
+/// support concurrent access from multiple threads.
+pub trait PageStore: Send + Sync {
+    /// Allocates a new zeroed page and returns its id. Like writes, allocations and
+    /// deallocations are only durable after the next `sync`.
+    fn allocate_page(&self) -> Result<PageId>;
+
+    /// Deallocates the page, allowing a later `allocate_page` to reuse its id.
+    fn deallocate_page(&self, page_id: &PageId) -> Result<()>;
+
+    /// Reads the page with the given id. Returns `None` if the page has been deallocated.
+    fn read(&self, page_id: &PageId) -> Result<Option<Bytes>>;
+
+    /// Writes `data` to the start of the page, zero-filling the rest of it.
+    fn write(&self, page_id: &PageId, data: &[u8]) -> Result<()>;
+
+    /// Makes all completed writes durable.
+    fn sync(&self) -> Result<()>;
+
+    /// Returns the size of every page in bytes.
+    fn page_size(&self) -> usize;
+}
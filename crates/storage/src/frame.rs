@@ -1,77 +1,154 @@
-use crate::{
-    page::{INVALID_PAGE_ID, PAGE_SIZE},
-    typedef::PageId,
-};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
 
-pub(crate) struct PageFrame {
-    page_id: PageId,
-    is_dirty: bool,
-    pin_cnt: u16,
-    data: [u8; PAGE_SIZE],
+use bytes::Bytes;
+use parking_lot::{ArcRwLockReadGuard, ArcRwLockWriteGuard, Mutex, RawRwLock, RwLock};
+
+use crate::{page::INVALID_PAGE_ID, typedef::PageId, Result};
+
+pub(crate) type PageFrameReadGuard = ArcRwLockReadGuard<RawRwLock, PageFrame>;
+pub(crate) type PageFrameWriteGuard = ArcRwLockWriteGuard<RawRwLock, PageFrame>;
+/// The completion channel of a page read scheduled on the disk scheduler.
+pub(crate) type PendingRead = Receiver<Result<Option<Bytes>>>;
+
+/// A frame of the buffer pool. The page it holds is protected by the frame's own reader/writer
+/// latch, while the pin count and dirty flag are kept outside of the latch, so that the pool can
+/// pin and unpin the frame without waiting for the threads that hold the latch.
+pub(crate) struct FrameHeader {
+    pin_count: AtomicUsize,
+    /// Set when a writer releases the frame. Cleared when the page is copied for a write to
+    /// disk, which happens under the latch, so that a change made after the copy sets it again.
+    is_dirty: AtomicBool,
+    /// The read of a prefetched page that has not been copied into the frame yet. Only taken
+    /// while holding the exclusive latch.
+    pending_read: Mutex<Option<PendingRead>>,
+    /// Set when the read of the page failed and the page was removed from the pool, so that
+    /// the frame is freed rather than made evictable once its last pin is released.
+    discarded: AtomicBool,
+    page_frame: Arc<RwLock<PageFrame>>,
 }
 
-impl PageFrame {
-    /// Creates a new page with default values.
-    pub(crate) fn new() -> Self {
+impl FrameHeader {
+    pub(crate) fn new(page_size: usize) -> Self {
         Self {
-            page_id: INVALID_PAGE_ID,
-            is_dirty: false,
-            pin_cnt: 0,
-            data: [0; PAGE_SIZE],
+            pin_count: AtomicUsize::new(0),
+            is_dirty: AtomicBool::new(false),
+            pending_read: Mutex::new(None),
+            discarded: AtomicBool::new(false),
+            page_frame: Arc::new(RwLock::new(PageFrame::new(page_size))),
         }
     }
 
-    pub(crate) fn page_id(&self) -> PageId {
-        self.page_id
+    pub(crate) fn pin_count(&self) -> usize {
+        self.pin_count.load(Ordering::Acquire)
+    }
+
+    /// Increments the pin count and returns the new count.
+    pub(crate) fn pin(&self) -> usize {
+        self.pin_count.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// Decrements the pin count and returns the new count.
+    pub(crate) fn unpin(&self) -> usize {
+        let old_count = self.pin_count.fetch_sub(1, Ordering::AcqRel);
+        assert!(old_count != 0, "Frame is not pinned");
+        old_count - 1
     }
 
     pub(crate) fn is_dirty(&self) -> bool {
-        self.is_dirty
+        self.is_dirty.load(Ordering::Acquire)
     }
 
-    pub(crate) fn pin_count(&self) -> u16 {
-        self.pin_cnt
+    pub(crate) fn set_dirty(&self, dirty: bool) {
+        self.is_dirty.store(dirty, Ordering::Release);
     }
 
-    pub(crate) fn data(&self) -> &[u8] {
-        &self.data
+    /// Clears the dirty flag and returns whether it was set.
+    pub(crate) fn take_dirty(&self) -> bool {
+        self.is_dirty.swap(false, Ordering::AcqRel)
     }
 
-    pub(crate) fn data_mut(&mut self) -> &mut [u8] {
-        &mut self.data
+    pub(crate) fn has_pending_read(&self) -> bool {
+        self.pending_read.lock().is_some()
     }
 
-    pub(crate) fn set_page_id(&mut self, page_id: PageId) {
-        self.page_id = page_id;
+    pub(crate) fn set_pending_read(&self, read: PendingRead) {
+        *self.pending_read.lock() = Some(read);
+    }
+
+    pub(crate) fn take_pending_read(&self) -> Option<PendingRead> {
+        self.pending_read.lock().take()
+    }
+
+    pub(crate) fn discard(&self) {
+        self.discarded.store(true, Ordering::Release);
+    }
+
+    /// Clears the discarded flag and returns whether it was set.
+    pub(crate) fn take_discarded(&self) -> bool {
+        self.discarded.swap(false, Ordering::AcqRel)
+    }
+
+    /// Acquires the latch of the frame in shared mode.
+    pub(crate) fn read(&self) -> PageFrameReadGuard {
+        self.page_frame.read_arc()
+    }
+
+    /// Acquires the latch of the frame in exclusive mode.
+    pub(crate) fn write(&self) -> PageFrameWriteGuard {
+        self.page_frame.write_arc()
+    }
+
+    /// Acquires the latch of the frame in shared mode, unless that would block.
+    pub(crate) fn try_read(&self) -> Option<PageFrameReadGuard> {
+        self.page_frame.try_read_arc()
+    }
+
+    /// Acquires the latch of the frame in exclusive mode, unless that would block.
+    pub(crate) fn try_write(&self) -> Option<PageFrameWriteGuard> {
+        self.page_frame.try_write_arc()
+    }
+}
+
+pub(crate) struct PageFrame {
+    page_id: PageId,
+    data: Box<[u8]>,
+}
+
+impl PageFrame {
+    /// Creates a new page of `page_size` bytes with default values.
+    pub(crate) fn new(page_size: usize) -> Self {
+        Self {
+            page_id: INVALID_PAGE_ID,
+            data: vec![0; page_size].into_boxed_slice(),
+        }
     }
 
-    pub(crate) fn set_dirty(&mut self, dirty: bool) {
-        self.is_dirty = dirty;
+    pub(crate) fn page_id(&self) -> PageId {
+        self.page_id
     }
 
-    pub(crate) fn set_pin_count(&mut self, pin_cnt: u16) {
-        self.pin_cnt = pin_cnt;
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
     }
 
-    pub(crate) fn increment_pin_count(&mut self) {
-        self.pin_cnt += 1;
+    pub(crate) fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
     }
 
-    pub(crate) fn decrement_pin_count(&mut self) {
-        assert!(self.pin_cnt != 0);
-        self.pin_cnt -= 1;
+    pub(crate) fn set_page_id(&mut self, page_id: PageId) {
+        self.page_id = page_id;
     }
 
     pub(crate) fn reset(&mut self) {
         self.page_id = INVALID_PAGE_ID;
-        self.pin_cnt = 0;
-        self.is_dirty = false;
         self.data.fill(0);
     }
 
     /// Writes data to the page.
     pub(crate) fn write(&mut self, offset: usize, data: &[u8]) {
-        if offset + data.len() > PAGE_SIZE {
+        if offset + data.len() > self.data.len() {
             panic!("Write out of bounds");
         }
         self.data[offset..offset + data.len()].copy_from_slice(data);
@@ -1,94 +1,189 @@
 use crate::buffer_pool::BufferPoolManager;
-use crate::frame::PageFrame;
-use std::sync::{Arc, RwLock};
+use crate::frame::{PageFrame, PageFrameReadGuard, PageFrameWriteGuard};
+use crate::typedef::FrameId;
+use crate::Result;
 
-/// Immutable page handle for read access.
+/// Immutable page handle for read access. The handle holds the frame's latch in shared mode,
+/// so any number of `PageFrameRefHandle`s to a page can exist at the same time, but none
+/// while a [`PageFrameMutHandle`] to it exists. The latch is released and the page unpinned
+/// when the handle is dropped.
 pub struct PageFrameRefHandle<'a> {
-    bpm: &'a Arc<RwLock<BufferPoolManager>>,
-    page_frame: &'a PageFrame,
+    bpm: &'a BufferPoolManager,
+    frame_id: FrameId,
+    /// Only `None` while the handle is dropped.
+    page_frame: Option<PageFrameReadGuard>,
 }
 
 impl<'a> PageFrameRefHandle<'a> {
-    pub(crate) fn new(bpm: &'a Arc<RwLock<BufferPoolManager>>, page_frame: &'a PageFrame) -> Self {
-        PageFrameRefHandle { bpm, page_frame }
+    pub(crate) fn new(
+        bpm: &'a BufferPoolManager,
+        frame_id: FrameId,
+        page_frame: PageFrameReadGuard,
+    ) -> Self {
+        PageFrameRefHandle {
+            bpm,
+            frame_id,
+            page_frame: Some(page_frame),
+        }
     }
 
     pub(crate) fn page_frame(&self) -> &PageFrame {
-        self.page_frame
+        self.page_frame.as_ref().unwrap()
     }
 }
 
-impl<'a> Drop for PageFrameRefHandle<'a> {
+impl Drop for PageFrameRefHandle<'_> {
     fn drop(&mut self) {
-        self.bpm
-            .write()
-            .unwrap()
-            .unpin_page(&self.page_frame.page_id(), false);
+        // release the latch before unpinning, so the latch of an unpinned frame is never held
+        self.page_frame.take();
+        self.bpm.unpin_frame(self.frame_id, false);
     }
 }
 
-/// Mutable page handle for write access.
+/// Mutable page handle for write access. The handle holds the frame's latch in exclusive
+/// mode, so no other handle to the page exists at the same time. The page is marked dirty,
+/// the latch released and the page unpinned when the handle is dropped.
 pub struct PageFrameMutHandle<'a> {
-    bpm: &'a Arc<RwLock<BufferPoolManager>>,
-    page_frame: &'a mut PageFrame,
+    bpm: &'a BufferPoolManager,
+    frame_id: FrameId,
+    /// Only `None` while the handle is dropped or deleted.
+    page_frame: Option<PageFrameWriteGuard>,
 }
 
 impl<'a> PageFrameMutHandle<'a> {
     pub(crate) fn new(
-        bpm: &'a Arc<RwLock<BufferPoolManager>>,
-        page_frame: &'a mut PageFrame,
+        bpm: &'a BufferPoolManager,
+        frame_id: FrameId,
+        page_frame: PageFrameWriteGuard,
     ) -> Self {
-        PageFrameMutHandle { bpm, page_frame }
+        PageFrameMutHandle {
+            bpm,
+            frame_id,
+            page_frame: Some(page_frame),
+        }
+    }
+
+    pub(crate) fn page_frame(&self) -> &PageFrame {
+        self.page_frame.as_ref().unwrap()
     }
 
     pub(crate) fn page_frame_mut(&mut self) -> &mut PageFrame {
-        self.page_frame
+        self.page_frame.as_mut().unwrap()
+    }
+
+    /// Deletes the page from both the buffer pool and disk. Fails with
+    /// [`rustdb_error::Error::PagePinned`] if another thread has the page pinned, in which case
+    /// the handle is released as if it was dropped.
+    pub(crate) fn delete(mut self) -> Result<()> {
+        let page_frame = self.page_frame.take().unwrap();
+        self.bpm.delete_latched_page(self.frame_id, page_frame)
     }
 }
 
-impl<'a> Drop for PageFrameMutHandle<'a> {
+impl Drop for PageFrameMutHandle<'_> {
     fn drop(&mut self) {
-        self.bpm
-            .write()
-            .unwrap()
-            .unpin_page(&self.page_frame.page_id(), true);
+        // the latch and pin were handed over if the page was deleted
+        if self.page_frame.take().is_some() {
+            self.bpm.unpin_frame(self.frame_id, true);
+        }
     }
 }
 
-impl<'a> AsRef<PageFrame> for PageFrameRefHandle<'a> {
+impl AsRef<PageFrame> for PageFrameRefHandle<'_> {
     fn as_ref(&self) -> &PageFrame {
-        self.page_frame
+        self.page_frame()
     }
 }
 
-impl<'a> AsMut<PageFrame> for PageFrameMutHandle<'a> {
+impl AsMut<PageFrame> for PageFrameMutHandle<'_> {
     fn as_mut(&mut self) -> &mut PageFrame {
-        self.page_frame
+        self.page_frame_mut()
     }
 }
 
-impl<'a> AsRef<PageFrame> for PageFrameMutHandle<'a> {
+impl AsRef<PageFrame> for PageFrameMutHandle<'_> {
     fn as_ref(&self) -> &PageFrame {
-        self.page_frame
+        self.page_frame()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::disk::disk_manager::DiskManager;
+    use crate::disk::memory_page_store::MemoryPageStore;
+    use crate::replacer::replacer::AccessType;
     use crate::{buffer_pool::BufferPoolManager, replacer::lru_replacer::LruReplacer};
-    use std::sync::{Arc, RwLock};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn setup() -> Arc<BufferPoolManager> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        Arc::new(BufferPoolManager::new(10, disk, replacer))
+    }
 
     #[test]
     fn test_mut_handle_unpins_on_drop() {
-        let disk = Arc::new(RwLock::new(DiskManager::new("test.db").unwrap()));
-        let replacer = Box::new(LruReplacer::new());
-        let bpm = Arc::new(RwLock::new(BufferPoolManager::new(10, disk, replacer)));
+        let bpm = setup();
+
+        let frame_id = {
+            let handle = BufferPoolManager::create_page_handle(&bpm).unwrap();
+            assert_eq!(1, bpm.pin_count(handle.frame_id));
+            handle.frame_id
+        };
+        assert_eq!(0, bpm.pin_count(frame_id));
+    }
+
+    #[test]
+    fn test_ref_handles_share_the_latch() {
+        let bpm = setup();
+        let page_id = BufferPoolManager::create_page_handle(&bpm)
+            .unwrap()
+            .page_frame()
+            .page_id();
+
+        let handle_1 =
+            BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown).unwrap();
+        let handle_2 =
+            BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown).unwrap();
+        assert_eq!(2, bpm.pin_count(handle_1.frame_id));
 
-        {
-            let handle = BufferPoolManager::create_page_handle(&bpm);
-            let cnt = handle.unwrap().page_frame.pin_count();
-            assert_eq!(1, cnt);
+        drop(handle_1);
+        assert_eq!(1, bpm.pin_count(handle_2.frame_id));
+    }
+
+    #[test]
+    fn test_mut_handle_excludes_other_handles() {
+        let bpm = setup();
+        let mut handle = BufferPoolManager::create_page_handle(&bpm).unwrap();
+        let page_id = handle.page_frame().page_id();
+
+        let fetched = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let bpm = bpm.clone();
+            let fetched = fetched.clone();
+            thread::spawn(move || {
+                let handle =
+                    BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown)
+                        .unwrap();
+                fetched.store(true, Ordering::Release);
+                handle.page_frame().data()[0]
+            })
+        };
+
+        // the reader pins the page, but waits for the latch until the writer is done
+        while bpm.pin_count(handle.frame_id) < 2 {
+            thread::yield_now();
         }
+        thread::sleep(Duration::from_millis(20));
+        assert!(!fetched.load(Ordering::Acquire));
+
+        handle.page_frame_mut().write(0, &[42]);
+        drop(handle);
+
+        assert_eq!(42, reader.join().unwrap());
+        assert_eq!(1, bpm.stats().pin_waits);
+        assert!(BufferPoolManager::fetch_page_mut_handle(&bpm, &page_id).is_ok());
     }
 }
@@ -1,4 +1,4 @@
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 
 use rustdb_error::Error;
 
@@ -6,7 +6,8 @@ use crate::page::INVALID_PAGE_ID;
 use crate::{
     buffer_pool::BufferPoolManager,
     page::table_page::{TablePageMut, TablePageRef, TupleMetadata},
-    record_id::RecordId,
+    record_id::{RecordId, RECORD_ID_SIZE},
+    replacer::replacer::AccessType,
     tuple::Tuple,
     typedef::PageId,
     Result,
@@ -16,69 +17,157 @@ use super::table_page_iterator::TablePageIterator;
 
 pub struct TableHeap {
     page_cnt: u32,
-    bpm: Arc<RwLock<BufferPoolManager>>,
+    bpm: Arc<BufferPoolManager>,
     first_page_id: PageId,
     last_page_id: PageId,
 }
 
 impl TableHeap {
     /// Create a new table heap. A new root page is allocated from the buffer pool.
-    pub fn new(bpm: Arc<RwLock<BufferPoolManager>>) -> TableHeap {
+    pub fn new(bpm: Arc<BufferPoolManager>) -> Result<TableHeap> {
         // Create the first (root) page.
         let first_page_id = {
-            let root_page_handle = BufferPoolManager::create_page_handle(&bpm)
-                .expect("Failed to create root page for table heap");
+            let root_page_handle = BufferPoolManager::create_page_handle(&bpm)?;
             let mut table_page = TablePageMut::from(root_page_handle);
             table_page.init_header(INVALID_PAGE_ID);
             table_page.page_id()
         };
 
-        TableHeap {
+        Ok(TableHeap {
             page_cnt: 1,
             bpm,
             first_page_id,
             last_page_id: first_page_id,
-        }
+        })
     }
 
     pub(crate) fn first_page_id(&self) -> PageId {
         self.first_page_id
     }
 
-    /// Retrieve a tuple given its record id.
+    /// Retrieve a tuple given its record id, following the redirect left in its slot if an
+    /// update moved the tuple to another page.
     pub fn get_tuple(&self, rid: &RecordId) -> Result<(TupleMetadata, Tuple)> {
+        let (metadata, tuple) = self.get_slot(rid)?;
+        if Self::follows_redirect(&metadata) {
+            return self.get_slot(&RecordId::from_bytes(tuple.data())?);
+        }
+        Ok((metadata, tuple))
+    }
+
+    /// Whether a slot holds a redirect to a moved tuple. Once a redirect is deleted, the slot it
+    /// points at may be reused by another tuple, so it must not be followed anymore.
+    fn follows_redirect(metadata: &TupleMetadata) -> bool {
+        metadata.is_redirect() && !metadata.is_deleted()
+    }
+
+    /// Retrieve the tuple stored in the slot of a record id, without following redirects.
+    fn get_slot(&self, rid: &RecordId) -> Result<(TupleMetadata, Tuple)> {
         // Fetch an immutable handle to the page where the tuple should reside.
-        let page_handle = BufferPoolManager::fetch_page_handle(&self.bpm, &rid.page_id())?;
+        let page_handle =
+            BufferPoolManager::fetch_page_handle(&self.bpm, &rid.page_id(), AccessType::Lookup)?;
         let table_page_ref = TablePageRef::from(page_handle);
         table_page_ref.get_tuple(rid)
     }
 
     /// Delete a tuple given its record id and return the deleted tuple data and tuple meatdata.
     pub fn delete_tuple(&self, rid: &RecordId) -> Result<(TupleMetadata, Tuple)> {
-        let old_data = self.get_tuple(rid)?;
-        let page_id = rid.page_id();
-        let page_handle = BufferPoolManager::fetch_page_mut_handle(&self.bpm, &page_id)?;
+        let (metadata, tuple) = self.get_slot(rid)?;
+        let old_data = if Self::follows_redirect(&metadata) {
+            // The relocated tuple is deleted along with its redirect.
+            let target = RecordId::from_bytes(tuple.data())?;
+            let old_data = self.get_slot(&target)?;
+            self.mark_deleted(&target, old_data.0)?;
+            old_data
+        } else {
+            (metadata, tuple)
+        };
+        self.mark_deleted(rid, metadata)?;
+
+        Ok(old_data)
+    }
+
+    fn mark_deleted(&self, rid: &RecordId, mut metadata: TupleMetadata) -> Result<()> {
+        let page_handle = BufferPoolManager::fetch_page_mut_handle(&self.bpm, &rid.page_id())?;
         let mut table_page_mut = TablePageMut::from(page_handle);
+        metadata.set_deleted(true);
+        table_page_mut.update_tuple_metadata(rid, metadata)
+    }
 
-        let mut deleted_metadata = old_data.0.clone();
-        deleted_metadata.set_deleted(true);
-        table_page_mut.update_tuple_metadata(rid, deleted_metadata)?;
+    /// Update a tuple given its record id. The tuple is updated in its page if it fits there,
+    /// otherwise it is moved to another page and a redirect to it is left in its slot, so that
+    /// the record id stays valid.
+    pub fn update_tuple(&mut self, rid: &RecordId, tuple: &Tuple) -> Result<()> {
+        let (metadata, old_tuple) = self.get_slot(rid)?;
 
-        Ok(old_data)
+        if Self::follows_redirect(&metadata) {
+            let target = RecordId::from_bytes(old_tuple.data())?;
+            if self.update_slot(&target, &TupleMetadata::relocated(), tuple)? {
+                return Ok(());
+            }
+            // The tuple outgrew the page it was moved to, so it is moved again. The redirect
+            // is repointed rather than chained, so reads never follow more than one.
+            let new_target = self.insert_tuple_with_metadata(&TupleMetadata::relocated(), tuple)?;
+            self.redirect(rid, &new_target)?;
+            return self.mark_deleted(&target, TupleMetadata::relocated());
+        }
+
+        if self.update_slot(rid, &metadata, tuple)? {
+            return Ok(());
+        }
+        // Check that the redirect fits before moving the tuple. The tuple does not fit this
+        // page, so inserting it elsewhere does not take the room of the redirect.
+        let page_handle =
+            BufferPoolManager::fetch_page_handle(&self.bpm, &rid.page_id(), AccessType::Lookup)?;
+        if !TablePageRef::from(page_handle).update_fits(rid, RECORD_ID_SIZE)? {
+            return Err(Error::OutOfBounds);
+        }
+        let target = self.insert_tuple_with_metadata(&TupleMetadata::relocated(), tuple)?;
+        self.redirect(rid, &target)
+    }
+
+    /// Update the tuple stored in the slot of a record id within its page. Returns false if the
+    /// tuple does not fit the page.
+    fn update_slot(&self, rid: &RecordId, metadata: &TupleMetadata, tuple: &Tuple) -> Result<bool> {
+        let page_handle = BufferPoolManager::fetch_page_mut_handle(&self.bpm, &rid.page_id())?;
+        let mut table_page_mut = TablePageMut::from(page_handle);
+        match table_page_mut.update_tuple(rid, metadata, tuple) {
+            Ok(()) => Ok(true),
+            Err(Error::OutOfBounds) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Points the slot of a record id at its tuple, which was moved to `target`. If the
+    /// redirect does not fit the slot's page, the moved tuple is deleted again and the update
+    /// fails with [`Error::OutOfBounds`].
+    fn redirect(&self, rid: &RecordId, target: &RecordId) -> Result<()> {
+        let redirect = Tuple::new(target.to_bytes().to_vec());
+        if self.update_slot(rid, &TupleMetadata::redirect(), &redirect)? {
+            return Ok(());
+        }
+        self.mark_deleted(target, TupleMetadata::relocated())?;
+        Err(Error::OutOfBounds)
     }
 
     /// Insert a tuple into the table heap.
     pub fn insert_tuple(&mut self, tuple: &Tuple) -> Result<RecordId> {
         // For a newly inserted tuple the metadata is by default not deleted
-        let metadata = TupleMetadata::new(false);
+        self.insert_tuple_with_metadata(&TupleMetadata::new(false), tuple)
+    }
 
+    fn insert_tuple_with_metadata(
+        &mut self,
+        metadata: &TupleMetadata,
+        tuple: &Tuple,
+    ) -> Result<RecordId> {
         // Try to fetch a mutable handle for the current last page.
         let last_page = self.last_page_id;
         let page_handle = BufferPoolManager::fetch_page_mut_handle(&self.bpm, &last_page)?;
         let mut table_page = TablePageMut::from(page_handle);
 
         // Try inserting the tuple into the current page.
-        match table_page.insert_tuple(&metadata, tuple) {
+        match table_page.insert_tuple(metadata, tuple) {
             Ok(rid) => Ok(rid),
             // If there isn’t enough free space
             Err(Error::OutOfBounds) => {
@@ -95,7 +184,7 @@ impl TableHeap {
                 new_table_page.init_header(INVALID_PAGE_ID);
 
                 // Try inserting the tuple into the new page.
-                let rid = new_table_page.insert_tuple(&metadata, tuple)?;
+                let rid = new_table_page.insert_tuple(metadata, tuple)?;
                 // Update the table heap’s bookkeeping.
                 self.last_page_id = new_page_id;
                 self.page_cnt += 1;
@@ -104,30 +193,37 @@ impl TableHeap {
             Err(e) => Err(e),
         }
     }
-    pub fn page_iter(&self) -> TablePageIterator {
+    pub fn page_iter(&self) -> TablePageIterator<'_> {
         TablePageIterator::new(&self.bpm, self.first_page_id())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::{Arc, RwLock};
+    use std::sync::Arc;
 
-    use crate::disk::disk_manager::DiskManager;
+    use crate::disk::fault_injecting_page_store::FaultInjectingPageStore;
+    use crate::disk::memory_page_store::MemoryPageStore;
     use crate::heap::table_heap::TableHeap;
-    use crate::page::table_page::{TABLE_PAGE_HEADER_SIZE, TUPLE_INFO_SIZE};
-    use crate::page::PAGE_SIZE;
+    use crate::heap::table_tuple_iterator::TableTupleIterator;
+    use crate::page::table_page::{
+        TablePageRef, TupleMetadata, TABLE_PAGE_HEADER_SIZE, TUPLE_INFO_SIZE,
+    };
+    use crate::page::{page_data_size, DEFAULT_PAGE_SIZE};
+    use crate::record_id::RecordId;
     use crate::replacer::lru_replacer::LruReplacer;
+    use crate::replacer::replacer::AccessType;
     use crate::{buffer_pool::BufferPoolManager, tuple::Tuple, Result};
+    use rustdb_error::Error;
 
     /// Test that we can insert a tuple into the table heap and then retrieve it correctly.
     #[test]
     fn test_table_heap_insert_and_get() -> Result<()> {
-        let disk = Arc::new(RwLock::new(DiskManager::new("test.db").unwrap()));
+        let disk = Arc::new(MemoryPageStore::new());
         let replacer = Box::new(LruReplacer::new());
-        let bpm = Arc::new(RwLock::new(BufferPoolManager::new(10, disk, replacer)));
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
 
-        let mut table_heap = TableHeap::new(bpm.clone());
+        let mut table_heap = TableHeap::new(bpm.clone())?;
 
         let tuple_data = vec![10, 20, 30, 40, 50];
         let tuple = Tuple::new(tuple_data.clone());
@@ -144,14 +240,15 @@ mod tests {
     /// triggers allocation of a new page and that both tuples are correctly stored.
     #[test]
     fn test_table_heap_new_page_allocation() -> Result<()> {
-        let disk = Arc::new(RwLock::new(DiskManager::new("test.db").unwrap()));
+        let disk = Arc::new(MemoryPageStore::new());
         let replacer = Box::new(LruReplacer::new());
-        let bpm = Arc::new(RwLock::new(BufferPoolManager::new(2, disk, replacer)));
+        let bpm = Arc::new(BufferPoolManager::new(2, disk, replacer));
 
-        let mut table_heap = TableHeap::new(bpm.clone());
+        let mut table_heap = TableHeap::new(bpm.clone())?;
 
         // Create and insert a huge tuple that nearly fills the page.
-        let huge_tuple_size = PAGE_SIZE - TABLE_PAGE_HEADER_SIZE - TUPLE_INFO_SIZE - 5;
+        let huge_tuple_size =
+            page_data_size(DEFAULT_PAGE_SIZE) - TABLE_PAGE_HEADER_SIZE - TUPLE_INFO_SIZE - 5;
         let huge_tuple_data = vec![1; huge_tuple_size];
         let huge_tuple = Tuple::new(huge_tuple_data.clone());
         let rid1 = table_heap.insert_tuple(&huge_tuple)?;
@@ -173,4 +270,253 @@ mod tests {
 
         Ok(())
     }
+
+    /// Test that a failing write while inserting surfaces as an IO error instead of a panic,
+    /// and that tuples already written stay readable.
+    #[test]
+    fn test_table_heap_write_failure() -> Result<()> {
+        let store = Arc::new(FaultInjectingPageStore::new(Arc::new(
+            MemoryPageStore::new(),
+        )));
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(2, store.clone(), replacer));
+
+        let mut table_heap = TableHeap::new(bpm.clone())?;
+        let tuple = Tuple::new(vec![1; page_data_size(DEFAULT_PAGE_SIZE) / 2]);
+        let rid = table_heap.insert_tuple(&tuple)?;
+        table_heap.insert_tuple(&tuple)?;
+
+        // The third tuple needs a third page, which has to evict the dirty root page.
+        store.fail_nth_write(1);
+        assert!(matches!(table_heap.insert_tuple(&tuple), Err(Error::IO(_))));
+
+        let (_meta, retrieved) = table_heap.get_tuple(&rid)?;
+        assert_eq!(tuple.data(), retrieved.data());
+
+        Ok(())
+    }
+
+    /// Test that creating a table heap on a crashed store returns an IO error.
+    #[test]
+    fn test_table_heap_new_after_crash() {
+        let store = Arc::new(FaultInjectingPageStore::new(Arc::new(
+            MemoryPageStore::new(),
+        )));
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(1, store.clone(), replacer));
+
+        store.crash();
+        assert!(matches!(TableHeap::new(bpm), Err(Error::IO(_))));
+    }
+
+    /// Test that tuples inserted before flushing the buffer pool survive a crash.
+    #[test]
+    fn test_table_heap_survives_crash_after_flush() -> Result<()> {
+        let store = Arc::new(FaultInjectingPageStore::new(Arc::new(
+            MemoryPageStore::new(),
+        )));
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(10, store.clone(), replacer));
+
+        let mut table_heap = TableHeap::new(bpm.clone())?;
+        let tuples: Vec<_> = (0..3u8)
+            .map(|i| Tuple::new(vec![i; page_data_size(DEFAULT_PAGE_SIZE) / 2]))
+            .collect();
+        let rids = tuples
+            .iter()
+            .map(|tuple| table_heap.insert_tuple(tuple))
+            .collect::<Result<Vec<_>>>()?;
+
+        bpm.flush_all_pages()?;
+        store.crash();
+
+        let replacer = Box::new(LruReplacer::new());
+        let recovered = Arc::new(BufferPoolManager::new(10, store.inner().clone(), replacer));
+        for (rid, tuple) in rids.iter().zip(&tuples) {
+            let handle = BufferPoolManager::fetch_page_handle(
+                &recovered,
+                &rid.page_id(),
+                AccessType::Lookup,
+            )?;
+            let (_meta, retrieved) = TablePageRef::from(handle).get_tuple(rid)?;
+            assert_eq!(tuple.data(), retrieved.data());
+        }
+
+        Ok(())
+    }
+
+    /// Test that a table heap on large pages fits tuples that would not fit a default page.
+    #[test]
+    fn test_table_heap_with_large_pages() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::with_page_size(65536));
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(2, disk, replacer));
+
+        let mut table_heap = TableHeap::new(bpm.clone())?;
+        let tuple = Tuple::new(vec![7; 20000]);
+        let rid1 = table_heap.insert_tuple(&tuple)?;
+        let rid2 = table_heap.insert_tuple(&tuple)?;
+        assert_eq!(rid1.page_id(), rid2.page_id());
+
+        let (_meta, retrieved) = table_heap.get_tuple(&rid2)?;
+        assert_eq!(tuple.data(), retrieved.data());
+
+        Ok(())
+    }
+
+    /// Test that updates that fit the page of a tuple keep it in its slot.
+    #[test]
+    fn test_table_heap_update_in_page() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
+
+        let mut table_heap = TableHeap::new(bpm.clone())?;
+        let rids = (0..3u8)
+            .map(|i| table_heap.insert_tuple(&Tuple::new(vec![i; 100])))
+            .collect::<Result<Vec<_>>>()?;
+
+        // smaller tuples are overwritten in place, larger ones move within the page
+        table_heap.update_tuple(&rids[1], &Tuple::new(vec![7; 50]))?;
+        assert_eq!(&vec![7; 50], table_heap.get_tuple(&rids[1])?.1.data());
+        table_heap.update_tuple(&rids[1], &Tuple::new(vec![8; 1000]))?;
+        assert_eq!(&vec![8; 1000], table_heap.get_tuple(&rids[1])?.1.data());
+
+        let handle =
+            BufferPoolManager::fetch_page_handle(&bpm, &rids[1].page_id(), AccessType::Lookup)?;
+        let (meta, _) = TablePageRef::from(handle).get_tuple(&rids[1])?;
+        assert!(!meta.is_redirect());
+        for i in [0, 2] {
+            assert_eq!(
+                &vec![i as u8; 100],
+                table_heap.get_tuple(&rids[i])?.1.data()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Test that a tuple that outgrows its page is moved to another page, and stays reachable
+    /// through its record id.
+    #[test]
+    fn test_table_heap_update_moves_tuple() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
+
+        let mut table_heap = TableHeap::new(bpm.clone())?;
+        let tuple_size = page_data_size(DEFAULT_PAGE_SIZE) / 3;
+        let rids = (0..2u8)
+            .map(|i| table_heap.insert_tuple(&Tuple::new(vec![i; tuple_size])))
+            .collect::<Result<Vec<_>>>()?;
+        let first_page_id = rids[0].page_id();
+
+        table_heap.update_tuple(&rids[0], &Tuple::new(vec![5; 2 * tuple_size]))?;
+        assert_eq!(
+            &vec![5; 2 * tuple_size],
+            table_heap.get_tuple(&rids[0])?.1.data()
+        );
+        assert_eq!(
+            &vec![1; tuple_size],
+            table_heap.get_tuple(&rids[1])?.1.data()
+        );
+
+        // a tuple that outgrows the page it was moved to is moved again, and the redirect in
+        // its original slot points at its new location
+        let other_rid = table_heap.insert_tuple(&Tuple::new(vec![2; 1000]))?;
+        assert_ne!(first_page_id, other_rid.page_id());
+        table_heap.update_tuple(&rids[0], &Tuple::new(vec![6; 2 * tuple_size + 500]))?;
+        assert_eq!(
+            &vec![6; 2 * tuple_size + 500],
+            table_heap.get_tuple(&rids[0])?.1.data()
+        );
+
+        let handle =
+            BufferPoolManager::fetch_page_handle(&bpm, &first_page_id, AccessType::Lookup)?;
+        let (meta, redirect) = TablePageRef::from(handle).get_tuple(&rids[0])?;
+        assert!(meta.is_redirect());
+        let target = RecordId::from_bytes(redirect.data())?;
+        assert_ne!(first_page_id, target.page_id());
+        assert_ne!(other_rid.page_id(), target.page_id());
+
+        // scans return the moved tuple once, under its original record id
+        let tuples =
+            TableTupleIterator::new(bpm.clone(), &table_heap).collect::<Result<Vec<_>>>()?;
+        let scanned_rids: Vec<_> = tuples.iter().map(|(rid, _)| rid.clone()).collect();
+        assert_eq!(
+            vec![rids[0].clone(), rids[1].clone(), other_rid],
+            scanned_rids
+        );
+        assert_eq!(&vec![6; 2 * tuple_size + 500], tuples[0].1.data());
+
+        // deleting the tuple deletes the moved tuple as well
+        table_heap.delete_tuple(&rids[0])?;
+        assert!(table_heap.get_tuple(&rids[0])?.0.is_deleted());
+        assert_eq!(2, TableTupleIterator::new(bpm.clone(), &table_heap).count());
+
+        Ok(())
+    }
+
+    /// Test that a moved tuple is deleted again if its redirect does not fit its original page.
+    #[test]
+    fn test_table_heap_redirect_that_does_not_fit() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
+
+        let mut table_heap = TableHeap::new(bpm.clone())?;
+        let rid = table_heap.insert_tuple(&Tuple::new(vec![1]))?;
+        let filler_size =
+            page_data_size(DEFAULT_PAGE_SIZE) - TABLE_PAGE_HEADER_SIZE - 2 * TUPLE_INFO_SIZE - 1;
+        table_heap.insert_tuple(&Tuple::new(vec![2; filler_size]))?;
+
+        assert!(matches!(
+            table_heap.update_tuple(&rid, &Tuple::new(vec![3; 100])),
+            Err(Error::OutOfBounds)
+        ));
+
+        let target = table_heap
+            .insert_tuple_with_metadata(&TupleMetadata::relocated(), &Tuple::new(vec![3; 100]))?;
+        assert!(matches!(
+            table_heap.redirect(&rid, &target),
+            Err(Error::OutOfBounds)
+        ));
+        assert!(table_heap.get_tuple(&target)?.0.is_deleted());
+        assert_eq!(&vec![1], table_heap.get_tuple(&rid)?.1.data());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_heap_deleted_redirect_is_not_followed() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
+
+        let mut table_heap = TableHeap::new(bpm.clone())?;
+        let tuple_size = page_data_size(DEFAULT_PAGE_SIZE) / 3;
+        let rids = (0..2u8)
+            .map(|i| table_heap.insert_tuple(&Tuple::new(vec![i; tuple_size])))
+            .collect::<Result<Vec<_>>>()?;
+        table_heap.update_tuple(&rids[0], &Tuple::new(vec![5; 2 * tuple_size]))?;
+        table_heap.delete_tuple(&rids[0])?;
+
+        // the slot the tuple was moved to is reused by another tuple
+        let other_rid = table_heap.insert_tuple(&Tuple::new(vec![6; 2 * tuple_size]))?;
+        let handle =
+            BufferPoolManager::fetch_page_handle(&bpm, &rids[0].page_id(), AccessType::Lookup)?;
+        let (_, redirect) = TablePageRef::from(handle).get_tuple(&rids[0])?;
+        assert_eq!(other_rid, RecordId::from_bytes(redirect.data())?);
+
+        assert!(table_heap.get_tuple(&rids[0])?.0.is_deleted());
+        assert!(table_heap.delete_tuple(&rids[0])?.0.is_deleted());
+        assert!(table_heap
+            .update_tuple(&rids[0], &Tuple::new(vec![7]))
+            .is_err());
+        let (meta, tuple) = table_heap.get_tuple(&other_rid)?;
+        assert!(!meta.is_deleted());
+        assert_eq!(&vec![6; 2 * tuple_size], tuple.data());
+
+        Ok(())
+    }
 }
@@ -1,23 +1,66 @@
-use std::sync::{Arc, RwLock};
+use std::collections::VecDeque;
 
 use crate::{
     buffer_pool::BufferPoolManager,
     page::{table_page::TablePageRef, INVALID_PAGE_ID},
+    replacer::replacer::AccessType,
     typedef::PageId,
     Result,
 };
 use rustdb_error::Error;
 
 pub struct TablePageIterator<'a> {
-    bpm: &'a Arc<RwLock<BufferPoolManager>>,
+    bpm: &'a BufferPoolManager,
     current_page_id: PageId,
+    prefetch_depth: usize,
+    /// The pages from `current_page_id` on that were prefetched, in chain order.
+    prefetched: VecDeque<PageId>,
 }
 
 impl<'a> TablePageIterator<'a> {
-    pub fn new(bpm: &'a Arc<RwLock<BufferPoolManager>>, first_page_id: PageId) -> Self {
+    pub fn new(bpm: &'a BufferPoolManager, first_page_id: PageId) -> Self {
         TablePageIterator {
             bpm,
             current_page_id: first_page_id,
+            prefetch_depth: 0,
+            prefetched: VecDeque::new(),
+        }
+    }
+
+    /// Reads up to `depth` pages ahead of the page returned last, so that reading them overlaps
+    /// with processing the pages before them. A page's successor is only known once the page is
+    /// read, so the iterator prefetches the successor of the last prefetched page once its read
+    /// has completed. With a scan ring, `depth` should be smaller than the ring, or prefetched
+    /// pages are evicted before they are returned. A depth of 0, the default, disables
+    /// prefetching.
+    pub fn with_prefetch_depth(mut self, depth: usize) -> Self {
+        self.prefetch_depth = depth;
+        self
+    }
+
+    /// Prefetches pages following `current_page_id`, until `prefetch_depth` pages are
+    /// prefetched or the read of the last prefetched page is still in flight.
+    fn prefetch(&mut self) {
+        while self.prefetched.len() < self.prefetch_depth {
+            let page_id = match self.prefetched.back() {
+                None => self.current_page_id,
+                Some(last_page_id) => {
+                    match self
+                        .bpm
+                        .try_fetch_page_handle(last_page_id, AccessType::Scan)
+                    {
+                        Some(handle) => TablePageRef::from(handle).next_page_id(),
+                        None => return,
+                    }
+                }
+            };
+            if page_id == INVALID_PAGE_ID {
+                return;
+            }
+
+            // prefetching is only a hint, a failure shows when the page is fetched
+            let _ = self.bpm.prefetch_page(&page_id, AccessType::Scan);
+            self.prefetched.push_back(page_id);
         }
     }
 }
@@ -30,20 +73,27 @@ impl<'a> Iterator for TablePageIterator<'a> {
             return None;
         }
 
-        let new_handle =
-            match BufferPoolManager::fetch_page_handle(&self.bpm, &self.current_page_id) {
-                Ok(handle) => handle,
-                Err(e) => {
-                    return Some(Err(Error::IO(format!(
-                        "Failed to fetch page {}: {}",
-                        self.current_page_id, e
-                    ))));
-                }
-            };
+        let new_handle = match BufferPoolManager::fetch_page_handle(
+            self.bpm,
+            &self.current_page_id,
+            AccessType::Scan,
+        ) {
+            Ok(handle) => handle,
+            Err(e) => {
+                return Some(Err(Error::IO(format!(
+                    "Failed to fetch page {}: {}",
+                    self.current_page_id, e
+                ))));
+            }
+        };
 
         let table_page = TablePageRef::from(new_handle);
 
+        if self.prefetched.front() == Some(&self.current_page_id) {
+            self.prefetched.pop_front();
+        }
         self.current_page_id = table_page.next_page_id();
+        self.prefetch();
 
         Some(Ok(table_page))
     }
@@ -51,22 +101,27 @@ impl<'a> Iterator for TablePageIterator<'a> {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::{Arc, RwLock};
+    use std::sync::Arc;
 
     use crate::{
-        buffer_pool::BufferPoolManager, disk::disk_manager::DiskManager,
-        heap::table_heap::TableHeap, replacer::lru_replacer::LruReplacer, tuple::Tuple, Result,
+        buffer_pool::BufferPoolManager,
+        disk::memory_page_store::MemoryPageStore,
+        heap::table_heap::TableHeap,
+        page::{page_data_size, DEFAULT_PAGE_SIZE},
+        replacer::lru_replacer::LruReplacer,
+        tuple::Tuple,
+        Result,
     };
 
     use super::{PageId, TablePageIterator};
 
     #[test]
     fn test_table_page_iterator() -> Result<()> {
-        let disk = Arc::new(RwLock::new(DiskManager::new("test.db").unwrap()));
+        let disk = Arc::new(MemoryPageStore::new());
         let replacer = Box::new(LruReplacer::new());
-        let bpm = Arc::new(RwLock::new(BufferPoolManager::new(10, disk, replacer)));
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
 
-        let mut table_heap = TableHeap::new(bpm.clone());
+        let mut table_heap = TableHeap::new(bpm.clone())?;
 
         let pages_wanted = 10;
         let mut first_page_id: Option<PageId> = None;
@@ -82,14 +137,40 @@ mod tests {
             }
         }
 
-        let mut iter = TablePageIterator::new(&bpm, table_heap.first_page_id());
+        let iter = TablePageIterator::new(&bpm, table_heap.first_page_id());
 
-        let mut current_page_id = first_page_id.unwrap();
-        while let Some(page) = iter.next() {
+        for (current_page_id, page) in (first_page_id.unwrap()..).zip(iter) {
             assert_eq!(current_page_id, page?.page_id());
-            current_page_id += 1;
         }
 
         Ok(())
     }
+
+    #[test]
+    fn test_table_page_iterator_prefetches_pages() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(4, disk, replacer));
+
+        let mut table_heap = TableHeap::new(bpm.clone())?;
+        let tuple = Tuple::new(vec![1; page_data_size(DEFAULT_PAGE_SIZE) / 2]);
+        let mut page_ids = vec![table_heap.first_page_id()];
+        while page_ids.len() < 10 {
+            let page_id = table_heap.insert_tuple(&tuple)?.page_id();
+            if page_id != *page_ids.last().unwrap() {
+                page_ids.push(page_id);
+            }
+        }
+        bpm.flush_all_pages()?;
+
+        let scanned = table_heap
+            .page_iter()
+            .with_prefetch_depth(2)
+            .map(|page| Ok(page?.page_id()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(page_ids, scanned);
+        assert!(bpm.stats().prefetches > 0);
+
+        Ok(())
+    }
 }
@@ -1,9 +1,9 @@
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 
 use crate::page::INVALID_PAGE_ID;
 use crate::{
     buffer_pool::BufferPoolManager, page::table_page::TablePageRef, record_id::RecordId,
-    tuple::Tuple, typedef::PageId, Result,
+    replacer::replacer::AccessType, tuple::Tuple, typedef::PageId, Result,
 };
 use rustdb_error::Error;
 
@@ -13,9 +13,10 @@ use super::table_heap::TableHeap;
 ///
 /// This iterator borrows a TableHeap (to obtain the starting page ID and BPM)
 /// and then walks the page chain (via each page’s header) while iterating over the
-/// tuple slots. Deleted tuples are skipped.
+/// tuple slots. Deleted tuples are skipped, and tuples moved to another page by an update are
+/// returned under their original record id.
 pub struct TableTupleIterator<'a> {
-    bpm: Arc<RwLock<BufferPoolManager>>,
+    bpm: Arc<BufferPoolManager>,
     table_heap: &'a TableHeap,
     current_page_id: PageId,
     current_slot: u16,
@@ -23,7 +24,7 @@ pub struct TableTupleIterator<'a> {
 
 impl<'a> TableTupleIterator<'a> {
     /// Creates a new `TableIterator` using the table heap’s starting page.
-    pub fn new(bpm: Arc<RwLock<BufferPoolManager>>, table_heap: &'a TableHeap) -> Self {
+    pub fn new(bpm: Arc<BufferPoolManager>, table_heap: &'a TableHeap) -> Self {
         Self {
             bpm,
             table_heap,
@@ -45,8 +46,11 @@ impl<'a> Iterator for TableTupleIterator<'a> {
 
             // Fetch header info from the current page.
             let (tuple_count, next_page_id) = {
-                let page_handle_res =
-                    BufferPoolManager::fetch_page_handle(&self.bpm, &self.current_page_id);
+                let page_handle_res = BufferPoolManager::fetch_page_handle(
+                    &self.bpm,
+                    &self.current_page_id,
+                    AccessType::Scan,
+                );
                 let page_handle = match page_handle_res {
                     Ok(handle) => handle,
                     _ => {
@@ -76,8 +80,11 @@ impl<'a> Iterator for TableTupleIterator<'a> {
 
             // Fetch the tuple from the current page.
             let tuple_result = {
-                let page_handle_res =
-                    BufferPoolManager::fetch_page_handle(&self.bpm, &self.current_page_id);
+                let page_handle_res = BufferPoolManager::fetch_page_handle(
+                    &self.bpm,
+                    &self.current_page_id,
+                    AccessType::Scan,
+                );
                 let page_handle = match page_handle_res {
                     Ok(handle) => handle,
                     _ => {
@@ -94,7 +101,12 @@ impl<'a> Iterator for TableTupleIterator<'a> {
 
             match tuple_result {
                 Ok((meta, tuple)) => {
-                    if !meta.is_deleted() {
+                    if meta.is_redirect() && !meta.is_deleted() {
+                        // Follow the redirect to the moved tuple, which is skipped on its page.
+                        let moved = self.table_heap.get_tuple(&rid);
+                        return Some(moved.map(|(_, tuple)| (rid, tuple)));
+                    }
+                    if !meta.is_deleted() && !meta.is_relocated() {
                         // Found a non-deleted tuple; return it.
                         return Some(Ok((rid, tuple)));
                     }
@@ -114,10 +126,10 @@ impl<'a> Iterator for TableTupleIterator<'a> {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::{Arc, RwLock};
+    use std::sync::Arc;
 
     use crate::{
-        buffer_pool::BufferPoolManager, disk::disk_manager::DiskManager,
+        buffer_pool::BufferPoolManager, disk::memory_page_store::MemoryPageStore,
         heap::table_heap::TableHeap, record_id::RecordId, replacer::lru_replacer::LruReplacer,
         tuple::Tuple, Result,
     };
@@ -128,11 +140,11 @@ mod tests {
     #[test]
     fn test_table_iterator() -> Result<()> {
         // Set up a test disk and buffer pool manager.
-        let disk = Arc::new(RwLock::new(DiskManager::new("test.db").unwrap()));
+        let disk = Arc::new(MemoryPageStore::new());
         let replacer = Box::new(LruReplacer::new());
-        let bpm = Arc::new(RwLock::new(BufferPoolManager::new(10, disk, replacer)));
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
 
-        let mut table_heap = TableHeap::new(bpm.clone());
+        let mut table_heap = TableHeap::new(bpm.clone())?;
 
         let tuple1 = Tuple::new(vec![1, 2, 3]);
         let tuple2 = Tuple::new(vec![4, 5, 6]);
@@ -163,10 +175,10 @@ mod tests {
 
     #[test]
     fn test_table_tuple_iterator_multiple_pages() -> Result<()> {
-        let disk = Arc::new(RwLock::new(DiskManager::new("test.db")?));
+        let disk = Arc::new(MemoryPageStore::new());
         let replacer = Box::new(LruReplacer::new());
-        let bpm = Arc::new(RwLock::new(BufferPoolManager::new(10, disk, replacer)));
-        let mut table_heap = TableHeap::new(bpm.clone());
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
+        let mut table_heap = TableHeap::new(bpm.clone())?;
 
         let pages_wanted = 10;
         let mut inserted = 0;
@@ -193,11 +205,9 @@ mod tests {
 
         let num_tuples = inserted_data.len();
 
-        assert_eq!(all_tuples.len(), num_tuples as usize);
-        for ((expected_data, expected_rid), (actual_rid, actual_tuple)) in inserted_data
-            .into_iter()
-            .zip(inserted_rid.into_iter())
-            .zip(all_tuples.into_iter())
+        assert_eq!(all_tuples.len(), num_tuples);
+        for ((expected_data, expected_rid), (actual_rid, actual_tuple)) in
+            inserted_data.into_iter().zip(inserted_rid).zip(all_tuples)
         {
             assert_eq!(expected_data, actual_tuple.data().to_vec());
             assert_eq!(expected_rid, actual_rid);
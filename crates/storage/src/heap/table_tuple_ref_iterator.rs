@@ -1,6 +1,10 @@
 use crate::{page::table_page::TablePageRef, record_id::RecordId, tuple::TupleRef, Result};
 
 /// An iterator over the tuples in a table page, returning zero-copy TupleRef values.
+///
+/// Tuples that an update moved to another page are skipped: the redirects left in their
+/// original slots as well as the moved copies. Use
+/// [`TableHeap::get_tuple`](super::table_heap::TableHeap::get_tuple) to follow a redirect.
 pub struct TableTupleIterator<'a> {
     /// A reference to the table page from which we are iterating.
     page: &'a TablePageRef<'a>,
@@ -20,29 +24,33 @@ impl<'a> Iterator for TableTupleIterator<'a> {
     type Item = Result<TupleRef<'a>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current_slot >= self.page.tuple_count() {
-            return None;
-        }
-
-        let rid = RecordId::new(self.page.page_id(), self.current_slot);
-        let tuple_ref = self.page.get_tuple_ref(&rid);
+        while self.current_slot < self.page.tuple_count() {
+            let rid = RecordId::new(self.page.page_id(), self.current_slot);
+            let tuple_ref = self.page.get_tuple_ref(&rid);
 
-        self.current_slot += 1;
+            self.current_slot += 1;
 
-        Some(tuple_ref)
+            match tuple_ref {
+                Ok(tuple_ref)
+                    if tuple_ref.metadata().is_redirect()
+                        || tuple_ref.metadata().is_relocated() => {}
+                tuple_ref => return Some(tuple_ref),
+            }
+        }
+        None
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::{Arc, RwLock};
+    use std::sync::Arc;
 
     use crate::{
         buffer_pool::BufferPoolManager,
-        disk::disk_manager::DiskManager,
-        heap::{table_heap::TableHeap, table_page_iterator::TablePageIterator},
-        page::table_page::TablePageRef,
-        replacer::lru_replacer::LruReplacer,
+        disk::memory_page_store::MemoryPageStore,
+        heap::table_heap::TableHeap,
+        page::{page_data_size, table_page::TablePageRef, DEFAULT_PAGE_SIZE},
+        replacer::{lru_replacer::LruReplacer, replacer::AccessType},
         tuple::{Tuple, TupleRef},
         Result,
     };
@@ -51,11 +59,11 @@ mod tests {
 
     #[test]
     fn test_table_tuple_iterator() -> Result<()> {
-        let disk = Arc::new(RwLock::new(DiskManager::new("test.db").unwrap()));
+        let disk = Arc::new(MemoryPageStore::new());
         let replacer = Box::new(LruReplacer::new());
-        let bpm = Arc::new(RwLock::new(BufferPoolManager::new(10, disk, replacer)));
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
 
-        let mut table_heap = TableHeap::new(bpm.clone());
+        let mut table_heap = TableHeap::new(bpm.clone())?;
 
         let t1_data = vec![10, 20, 30];
         let t2_data = vec![40, 50, 60];
@@ -70,13 +78,14 @@ mod tests {
 
         let first_page_id = table_heap.first_page_id();
 
-        let frame_handle = BufferPoolManager::fetch_page_handle(&bpm, &first_page_id)?;
+        let frame_handle =
+            BufferPoolManager::fetch_page_handle(&bpm, &first_page_id, AccessType::Scan)?;
         let table_page = TablePageRef::from(frame_handle);
 
-        let mut iter = TableTupleIterator::new(&table_page);
+        let iter = TableTupleIterator::new(&table_page);
 
         let mut collected: Vec<Vec<u8>> = Vec::new();
-        while let Some(tuple_result) = iter.next() {
+        for tuple_result in iter {
             let tuple_ref: TupleRef = tuple_result?;
             assert!(!tuple_ref.metadata().is_deleted());
             collected.push(tuple_ref.data().to_vec());
@@ -91,10 +100,10 @@ mod tests {
     }
     #[test]
     fn test_combined_page_and_tuple_iterators() -> Result<()> {
-        let disk = Arc::new(RwLock::new(DiskManager::new("test.db").unwrap()));
+        let disk = Arc::new(MemoryPageStore::new());
         let replacer = Box::new(LruReplacer::new());
-        let bpm = Arc::new(RwLock::new(BufferPoolManager::new(10, disk, replacer)));
-        let mut table_heap = TableHeap::new(bpm.clone());
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
+        let mut table_heap = TableHeap::new(bpm.clone())?;
 
         let pages_wanted = 30;
         let mut num_tuples = 0;
@@ -109,13 +118,13 @@ mod tests {
             }
         }
 
-        let mut page_iter = table_heap.page_iter();
+        let page_iter = table_heap.page_iter();
         let mut all_tuples: Vec<Vec<u8>> = Vec::new();
 
-        while let Some(page_result) = page_iter.next() {
+        for page_result in page_iter {
             let page: TablePageRef = page_result?;
-            let mut tuple_iter = TableTupleIterator::new(&page);
-            while let Some(tuple_result) = tuple_iter.next() {
+            let tuple_iter = TableTupleIterator::new(&page);
+            for tuple_result in tuple_iter {
                 let tuple_ref: TupleRef = tuple_result?;
                 assert!(!tuple_ref.metadata().is_deleted());
                 all_tuples.push(tuple_ref.data().to_vec());
@@ -123,10 +132,34 @@ mod tests {
         }
 
         assert_eq!(all_tuples.len(), num_tuples as usize);
-        for (expected, actual) in inserted_data.into_iter().zip(all_tuples.into_iter()) {
+        for (expected, actual) in inserted_data.into_iter().zip(all_tuples) {
             assert_eq!(expected, actual);
         }
 
         Ok(())
     }
+
+    #[test]
+    fn test_table_tuple_iterator_skips_moved_tuples() -> Result<()> {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
+        let mut table_heap = TableHeap::new(bpm.clone())?;
+
+        let tuple_size = page_data_size(DEFAULT_PAGE_SIZE) / 3;
+        let rid = table_heap.insert_tuple(&Tuple::new(vec![1; tuple_size]))?;
+        table_heap.insert_tuple(&Tuple::new(vec![2; tuple_size]))?;
+        table_heap.update_tuple(&rid, &Tuple::new(vec![3; 2 * tuple_size]))?;
+
+        let mut all_tuples: Vec<Vec<u8>> = Vec::new();
+        for page_result in table_heap.page_iter() {
+            let page: TablePageRef = page_result?;
+            for tuple_result in TableTupleIterator::new(&page) {
+                all_tuples.push(tuple_result?.data().to_vec());
+            }
+        }
+        assert_eq!(vec![vec![2; tuple_size]], all_tuples);
+
+        Ok(())
+    }
 }
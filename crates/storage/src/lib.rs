@@ -1,5 +1,7 @@
 #![allow(dead_code)]
+pub(crate) mod background_writer;
 pub(crate) mod buffer_pool;
+pub(crate) mod buffer_pool_stats;
 pub(crate) mod disk;
 pub(crate) mod frame;
 pub(crate) mod frame_handle;
@@ -1,6 +1,29 @@
-use crate::typedef::PageId;
+use crate::{disk::disk_manager::PAGE_CHECKSUM_SIZE, typedef::PageId, Result};
+use rustdb_error::errinput;
 
 pub(crate) mod table_page;
 
 pub(crate) const INVALID_PAGE_ID: PageId = PageId::MAX;
-pub(crate) const PAGE_SIZE: usize = 4096;
+/// Page size of databases that do not choose one.
+pub(crate) const DEFAULT_PAGE_SIZE: usize = 4096;
+pub(crate) const MIN_PAGE_SIZE: usize = 4096;
+/// Table pages address tuples with 16-bit offsets, which bounds the page size.
+pub(crate) const MAX_PAGE_SIZE: usize = 65536;
+
+/// Checks that `page_size` is a power of two between [`MIN_PAGE_SIZE`] and [`MAX_PAGE_SIZE`].
+pub(crate) fn validate_page_size(page_size: usize) -> Result<()> {
+    if !page_size.is_power_of_two() || !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+        return errinput!(
+            "Page size {} is not a power of two between {} and {}.",
+            page_size,
+            MIN_PAGE_SIZE,
+            MAX_PAGE_SIZE
+        );
+    }
+    Ok(())
+}
+
+/// Bytes of a page available to page layouts, the tail is reserved for the page checksum.
+pub(crate) const fn page_data_size(page_size: usize) -> usize {
+    page_size - PAGE_CHECKSUM_SIZE
+}
@@ -1,5 +1,5 @@
 use crate::frame_handle::{PageFrameMutHandle, PageFrameRefHandle};
-use crate::page::PAGE_SIZE;
+use crate::page::page_data_size;
 use crate::record_id::RecordId;
 use crate::tuple::{Tuple, TupleRef};
 use crate::Result;
@@ -38,18 +38,43 @@ impl TupleInfo {
 pub(crate) const TABLE_PAGE_HEADER_SIZE: usize = mem::size_of::<TablePageHeader>();
 pub(crate) const TUPLE_INFO_SIZE: usize = mem::size_of::<TupleInfo>();
 
+/// The tuple holds the record id its data was moved to by an update, see
+/// [`TupleMetadata::redirect`].
+const REDIRECT_FLAG: u8 = 1;
+/// The tuple was moved off its page by an update, see [`TupleMetadata::relocated`].
+const RELOCATED_FLAG: u8 = 1 << 1;
+
 #[repr(C)]
 #[derive(Pod, Zeroable, Copy, Clone)]
 pub struct TupleMetadata {
     is_deleted: u8,
-    _padding: [u8; 1],
+    flags: u8,
 }
 
 impl TupleMetadata {
     pub fn new(is_deleted: bool) -> Self {
         Self {
             is_deleted: is_deleted as u8,
-            _padding: [0; 1],
+            flags: 0,
+        }
+    }
+
+    /// Metadata of a forwarding pointer, which keeps the record id of a tuple valid after an
+    /// update moved the tuple to another page. Its data is the encoded record id of the
+    /// relocated tuple.
+    pub(crate) fn redirect() -> Self {
+        Self {
+            is_deleted: 0,
+            flags: REDIRECT_FLAG,
+        }
+    }
+
+    /// Metadata of a tuple moved to another page by an update. The tuple is only reachable
+    /// through the redirect left in its original slot, so scans skip it.
+    pub(crate) fn relocated() -> Self {
+        Self {
+            is_deleted: 0,
+            flags: RELOCATED_FLAG,
         }
     }
 
@@ -57,6 +82,14 @@ impl TupleMetadata {
         self.is_deleted != 0
     }
 
+    pub(crate) fn is_redirect(&self) -> bool {
+        self.flags & REDIRECT_FLAG != 0
+    }
+
+    pub(crate) fn is_relocated(&self) -> bool {
+        self.flags & RELOCATED_FLAG != 0
+    }
+
     pub(crate) fn set_deleted(&mut self, deleted: bool) {
         self.is_deleted = deleted as u8;
     }
@@ -119,25 +152,67 @@ impl<T: AsRef<PageFrame>> TablePage<T> {
         Ok((tuple_info.metadata, tuple))
     }
 
-    fn get_next_tuple_offset(&mut self, tuple: &Tuple) -> Result<u16> {
-        let slot_end_offset = match self.tuple_count() {
-            0 => PAGE_SIZE,
-            _ => {
-                let slot_array = self.slot_array();
-                let last_tuple_info = slot_array.last().unwrap();
-                last_tuple_info.offset as usize
-            }
-        };
+    /// Returns the number of bytes of the page available to the table page layout.
+    pub(crate) fn data_size(&self) -> usize {
+        page_data_size(self.page_frame_handle.as_ref().data().len())
+    }
 
-        let tuple_offset = slot_end_offset - tuple.tuple_size();
+    /// Returns the end of the slot array.
+    fn slots_end(&self) -> usize {
+        TABLE_PAGE_HEADER_SIZE + self.tuple_count() as usize * TUPLE_INFO_SIZE
+    }
 
-        if TABLE_PAGE_HEADER_SIZE + TUPLE_INFO_SIZE * (self.tuple_count() + 1) as usize
-            > tuple_offset
-        {
-            return Result::from(Err(Error::OutOfBounds));
+    /// Returns the start of the tuple data, which grows from the end of the page towards the
+    /// slot array. Free slots point at the end of the page, so they do not lower it.
+    fn tuple_data_start(&self) -> usize {
+        self.slot_array()
+            .iter()
+            .map(|slot| slot.offset as usize)
+            .fold(self.data_size(), usize::min)
+    }
+
+    /// Returns the bytes between the slot array and the tuple data.
+    pub(crate) fn contiguous_free_space(&self) -> usize {
+        self.tuple_data_start() - self.slots_end()
+    }
+
+    /// Returns the bytes that are free once the page is compacted, i.e. all bytes but those of
+    /// the header, the slot array and the live tuples.
+    pub(crate) fn free_space(&self) -> usize {
+        let live_bytes: usize = self
+            .slot_array()
+            .iter()
+            .filter(|slot| !slot.metadata.is_deleted())
+            .map(|slot| slot.size_bytes as usize)
+            .sum();
+        self.data_size() - self.slots_end() - live_bytes
+    }
+
+    /// Returns the first slot freed by compacting the page, which an insert can reuse.
+    fn free_slot(&self) -> Option<u16> {
+        if self.deleted_tuple_count() == 0 {
+            return None;
         }
+        self.slot_array()
+            .iter()
+            .position(|slot| slot.metadata.is_deleted() && slot.size_bytes == 0)
+            .map(|slot_id| slot_id as u16)
+    }
+
+    /// Returns whether a tuple of `tuple_size` bytes fits between the slot array and the tuple
+    /// data, along with a new slot unless it reuses a free one.
+    fn fits(&self, tuple_size: usize, reuses_slot: bool) -> bool {
+        let slot_size = if reuses_slot { 0 } else { TUPLE_INFO_SIZE };
+        slot_size + tuple_size <= self.contiguous_free_space()
+    }
 
-        Ok(tuple_offset as u16)
+    /// Returns whether the tuple of `rid` can be replaced by one of `tuple_size` bytes, if
+    /// need be by compacting the page.
+    pub(crate) fn update_fits(&self, rid: &RecordId, tuple_size: usize) -> Result<bool> {
+        self.validate_record_id(rid)?;
+        let slot = self.slot_array()[rid.slot_id() as usize];
+        let old_size = slot.size_bytes as usize;
+        Ok(tuple_size <= old_size || tuple_size <= self.free_space() + old_size)
     }
 
     fn validate_record_id(&self, rid: &RecordId) -> Result<()> {
@@ -211,38 +286,128 @@ impl<T: AsMut<PageFrame> + AsRef<PageFrame>> TablePage<T> {
         header.deleted_tuple_cnt = deleted_tuple_count;
     }
 
+    /// Inserts a tuple, reusing a slot freed by compaction if there is one. If the tuple only
+    /// fits once the bytes of deleted tuples are reclaimed, the page is compacted first.
     pub(crate) fn insert_tuple(&mut self, meta: &TupleMetadata, tuple: &Tuple) -> Result<RecordId> {
         let tuple_size = tuple.tuple_size();
-        let tuple_offset = self.get_next_tuple_offset(tuple)?;
-
-        // Ensure there's enough space
-        if tuple_offset as usize + tuple_size > PAGE_SIZE {
-            return Result::from(Err(Error::OutOfBounds));
+        let mut free_slot = self.free_slot();
+        if !self.fits(tuple_size, free_slot.is_some()) {
+            if self.free_space() == self.contiguous_free_space() {
+                return Err(Error::OutOfBounds);
+            }
+            self.compact();
+            free_slot = self.free_slot();
+            if !self.fits(tuple_size, free_slot.is_some()) {
+                return Err(Error::OutOfBounds);
+            }
         }
 
-        let tuple_count = self.header().tuple_cnt as usize;
-
-        // Write tuple data into the page
+        // Write tuple data into the page, right before the tuple data
+        let tuple_offset = self.tuple_data_start() - tuple_size;
         let page_data = self.page_frame_handle.as_mut().data_mut();
-        page_data[tuple_offset as usize..tuple_offset as usize + tuple_size]
-            .copy_from_slice(&tuple.data());
+        page_data[tuple_offset..tuple_offset + tuple_size].copy_from_slice(tuple.data());
 
-        // Update the slot array
         let new_slot = TupleInfo {
-            offset: tuple_offset,
+            offset: tuple_offset as u16,
             size_bytes: tuple_size as u16,
             metadata: *meta,
         };
+        let slot_id = match free_slot {
+            Some(slot_id) => {
+                self.slot_array_mut()[slot_id as usize] = new_slot;
+                let deleted_tuple_count = self.deleted_tuple_count() - 1;
+                self.set_deleted_tuple_count(deleted_tuple_count);
+                slot_id
+            }
+            None => {
+                // Extend the slot array
+                let slot_id = self.tuple_count();
+                self.set_tuple_count(slot_id + 1);
+                self.slot_array_mut()[slot_id as usize] = new_slot;
+                slot_id
+            }
+        };
+        if meta.is_deleted() {
+            let deleted_tuple_count = self.deleted_tuple_count() + 1;
+            self.set_deleted_tuple_count(deleted_tuple_count);
+        }
 
-        // Extend the slot array
-        let slot_start = TABLE_PAGE_HEADER_SIZE + tuple_count * TUPLE_INFO_SIZE;
-        let slot_end = slot_start + TUPLE_INFO_SIZE;
-        page_data[slot_start..slot_end].copy_from_slice(bytemuck::bytes_of(&new_slot));
+        Ok(RecordId::new(self.page_id(), slot_id))
+    }
 
-        let header = self.header_mut();
-        header.tuple_cnt += 1;
+    /// Replaces the data and metadata of a live tuple, keeping its slot. A tuple that is not
+    /// larger than the old one is overwritten in place, otherwise it is written to free space,
+    /// compacting the page first if needed. Fails with [`Error::OutOfBounds`], leaving the page
+    /// unchanged, if the tuple does not fit the page.
+    pub(crate) fn update_tuple(
+        &mut self,
+        rid: &RecordId,
+        meta: &TupleMetadata,
+        tuple: &Tuple,
+    ) -> Result<()> {
+        self.validate_record_id(rid)?;
+        let slot_id = rid.slot_id() as usize;
+        let slot = self.slot_array()[slot_id];
+        if slot.metadata.is_deleted() || meta.is_deleted() {
+            return Err(Error::InvalidInput(rid.to_string()));
+        }
 
-        Ok(RecordId::new(self.page_id(), tuple_count as u16))
+        let tuple_size = tuple.tuple_size();
+        let tuple_offset = if tuple_size <= slot.size_bytes as usize {
+            // The bytes past the new tuple are reclaimed by the next compaction.
+            slot.offset as usize
+        } else if self.update_fits(rid, tuple_size)? {
+            // Release the old bytes, so that compacting the page reclaims them too.
+            self.slot_array_mut()[slot_id].size_bytes = 0;
+            if tuple_size > self.contiguous_free_space() {
+                self.compact();
+            }
+            self.tuple_data_start() - tuple_size
+        } else {
+            return Err(Error::OutOfBounds);
+        };
+
+        let page_data = self.page_frame_handle.as_mut().data_mut();
+        page_data[tuple_offset..tuple_offset + tuple_size].copy_from_slice(tuple.data());
+        self.slot_array_mut()[slot_id] = TupleInfo {
+            offset: tuple_offset as u16,
+            size_bytes: tuple_size as u16,
+            metadata: *meta,
+        };
+
+        Ok(())
+    }
+
+    /// Removes the bytes of deleted tuples from the page by moving the live tuples to the end
+    /// of the page. Live tuples keep their slots, so their record ids stay valid, while the
+    /// slots of deleted tuples are freed for inserts to reuse.
+    pub(crate) fn compact(&mut self) {
+        let data_size = self.data_size();
+        let mut slots = self.slot_array().to_vec();
+
+        // Tuples only move towards the end of the page, so moving them from the last one on
+        // never overwrites a tuple that has not been moved yet.
+        let mut live_slot_ids: Vec<usize> = (0..slots.len())
+            .filter(|&slot_id| !slots[slot_id].metadata.is_deleted())
+            .collect();
+        live_slot_ids.sort_unstable_by_key(|&slot_id| std::cmp::Reverse(slots[slot_id].offset));
+
+        let page_data = self.page_frame_handle.as_mut().data_mut();
+        let mut tuple_end = data_size;
+        for slot_id in live_slot_ids {
+            let slot = &mut slots[slot_id];
+            let offset = slot.offset as usize;
+            let size = slot.size_bytes as usize;
+            tuple_end -= size;
+            page_data.copy_within(offset..offset + size, tuple_end);
+            slot.offset = tuple_end as u16;
+        }
+
+        for slot in slots.iter_mut().filter(|slot| slot.metadata.is_deleted()) {
+            slot.offset = data_size as u16;
+            slot.size_bytes = 0;
+        }
+        self.slot_array_mut().copy_from_slice(&slots);
     }
 
     pub(crate) fn update_tuple_metadata(
@@ -254,9 +419,16 @@ impl<T: AsMut<PageFrame> + AsRef<PageFrame>> TablePage<T> {
 
         let slot_array = self.slot_array_mut();
         let slot = &mut slot_array[rid.slot_id() as usize];
-
+        let was_deleted = slot.metadata.is_deleted();
         slot.metadata = metadata;
 
+        let deleted_tuple_count = match (was_deleted, metadata.is_deleted()) {
+            (false, true) => self.deleted_tuple_count() + 1,
+            (true, false) => self.deleted_tuple_count() - 1,
+            _ => return Ok(()),
+        };
+        self.set_deleted_tuple_count(deleted_tuple_count);
+
         Ok(())
     }
 }
@@ -280,30 +452,29 @@ impl<'a> From<PageFrameMutHandle<'a>> for TablePageMut<'a> {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::{Arc, RwLock};
+    use std::sync::Arc;
 
     use crate::{
-        buffer_pool::BufferPoolManager, disk::disk_manager::DiskManager, page::INVALID_PAGE_ID,
-        record_id::INVALID_RECORD_ID, replacer::lru_replacer::LruReplacer,
+        buffer_pool::BufferPoolManager,
+        disk::memory_page_store::MemoryPageStore,
+        page::INVALID_PAGE_ID,
+        replacer::{lru_replacer::LruReplacer, replacer::AccessType},
     };
 
     use super::*;
 
     #[test]
     fn test_table_page_with_buffer_pool() {
-        let disk = Arc::new(RwLock::new(DiskManager::new("test.db").unwrap()));
+        let disk = Arc::new(MemoryPageStore::new());
         let replacer = Box::new(LruReplacer::new());
-        let bpm = Arc::new(RwLock::new(BufferPoolManager::new(10, disk, replacer)));
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
 
-        let mut page_id = INVALID_PAGE_ID;
-        {
+        let page_id = {
             let frame_handle = BufferPoolManager::create_page_handle(&bpm).unwrap();
             let mut table_page = TablePageMut::from(frame_handle);
 
             table_page.init_header(2);
 
-            page_id = table_page.page_id();
-
             let header = table_page.header();
             assert_eq!(header.next_page_id, 2);
             assert_eq!(header.tuple_cnt, 0);
@@ -323,7 +494,7 @@ mod tests {
             slots_mut[1].metadata.set_deleted(true);
             assert_eq!(slots_mut[0].offset, 55);
             assert_eq!(slots_mut[1].offset, 11);
-            assert_eq!(slots_mut[1].metadata.is_deleted(), true);
+            assert!(slots_mut[1].metadata.is_deleted());
 
             table_page.header_mut().tuple_cnt = 3;
 
@@ -331,10 +502,13 @@ mod tests {
             assert_eq!(slots.len(), 3);
             assert_eq!(slots[0].offset, 55);
             assert_eq!(slots[1].offset, 11);
-            assert_eq!(slots[1].metadata.is_deleted(), true);
-        }
+            assert!(slots[1].metadata.is_deleted());
 
-        let frame_handle_1 = BufferPoolManager::fetch_page_handle(&bpm, &page_id).unwrap();
+            table_page.page_id()
+        };
+
+        let frame_handle_1 =
+            BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown).unwrap();
 
         let table_page1 = TablePageRef::from(frame_handle_1);
 
@@ -346,28 +520,23 @@ mod tests {
         assert_eq!(slots.len(), 3);
         assert_eq!(slots[0].offset, 55);
         assert_eq!(slots[1].offset, 11);
-        assert_eq!(slots[1].metadata.is_deleted(), true);
+        assert!(slots[1].metadata.is_deleted());
     }
 
     #[test]
     fn test_insert_and_get_tuple() {
-        let disk = Arc::new(RwLock::new(DiskManager::new("test.db").unwrap()));
+        let disk = Arc::new(MemoryPageStore::new());
         let replacer = Box::new(LruReplacer::new());
-        let bpm = Arc::new(RwLock::new(BufferPoolManager::new(10, disk, replacer)));
-
-        let mut page_id = INVALID_PAGE_ID;
-        let mut insert_record_id = INVALID_RECORD_ID;
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
 
         // tuple metadata
         let metadata = TupleMetadata::new(true);
 
         let tuple_data = vec![1, 2, 3, 1, 2, 3, 4, 5, 6, 7, 8];
-        {
+        let (page_id, insert_record_id) = {
             let frame_handle = BufferPoolManager::create_page_handle(&bpm).unwrap();
             let mut table_page = TablePageMut::from(frame_handle);
 
-            page_id = table_page.page_id();
-
             // Initialize page header
             table_page.init_header(2);
             assert_eq!(table_page.header().tuple_cnt, 0);
@@ -378,16 +547,17 @@ mod tests {
             let record_id = table_page.insert_tuple(&metadata, &tuple).unwrap();
             assert_eq!(table_page.tuple_count(), 1);
 
-            insert_record_id = record_id.clone();
-
             // Retrieve the tuple
             let (retrieved_meta, retrieved_tuple) = table_page.get_tuple(&record_id).unwrap();
 
             // Ensure retrieved tuple matches inserted tuple
             assert_eq!(retrieved_meta.is_deleted(), metadata.is_deleted());
             assert_eq!(retrieved_tuple.data(), &tuple_data);
-        }
-        let frame_handle_1 = BufferPoolManager::fetch_page_handle(&bpm, &page_id).unwrap();
+
+            (table_page.page_id(), record_id)
+        };
+        let frame_handle_1 =
+            BufferPoolManager::fetch_page_handle(&bpm, &page_id, AccessType::Unknown).unwrap();
 
         let table_page1 = TablePageRef::from(frame_handle_1);
         // Retrieve the tuple
@@ -397,4 +567,97 @@ mod tests {
         assert_eq!(retrieved_meta.is_deleted(), metadata.is_deleted());
         assert_eq!(retrieved_tuple.data(), &tuple_data);
     }
+
+    fn setup_page(bpm: &Arc<BufferPoolManager>) -> TablePageMut<'_> {
+        let frame_handle = BufferPoolManager::create_page_handle(bpm).unwrap();
+        let mut table_page = TablePageMut::from(frame_handle);
+        table_page.init_header(INVALID_PAGE_ID);
+        table_page
+    }
+
+    fn delete(table_page: &mut TablePageMut<'_>, rid: &RecordId) {
+        table_page
+            .update_tuple_metadata(rid, TupleMetadata::new(true))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_compact_keeps_live_tuples() {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
+        let mut table_page = setup_page(&bpm);
+
+        let metadata = TupleMetadata::new(false);
+        let rids: Vec<_> = (0..4u8)
+            .map(|i| {
+                let tuple = Tuple::new(vec![i; 100]);
+                table_page.insert_tuple(&metadata, &tuple).unwrap()
+            })
+            .collect();
+        delete(&mut table_page, &rids[1]);
+        delete(&mut table_page, &rids[2]);
+        assert_eq!(2, table_page.deleted_tuple_count());
+
+        let free_space = table_page.free_space();
+        assert_eq!(free_space - 200, table_page.contiguous_free_space());
+        table_page.compact();
+        assert_eq!(free_space, table_page.contiguous_free_space());
+
+        for i in [0, 3] {
+            let (meta, tuple) = table_page.get_tuple(&rids[i]).unwrap();
+            assert!(!meta.is_deleted());
+            assert_eq!(&vec![i as u8; 100], tuple.data());
+        }
+
+        // the freed slots are reused before the slot array grows
+        let rid = table_page
+            .insert_tuple(&metadata, &Tuple::new(vec![9; 50]))
+            .unwrap();
+        assert_eq!(rids[1], rid);
+        assert_eq!(4, table_page.tuple_count());
+        assert_eq!(1, table_page.deleted_tuple_count());
+        assert_eq!(&vec![9; 50], table_page.get_tuple(&rid).unwrap().1.data());
+        assert_eq!(
+            &vec![3; 100],
+            table_page.get_tuple(&rids[3]).unwrap().1.data()
+        );
+    }
+
+    #[test]
+    fn test_insert_compacts_full_page() {
+        let disk = Arc::new(MemoryPageStore::new());
+        let replacer = Box::new(LruReplacer::new());
+        let bpm = Arc::new(BufferPoolManager::new(10, disk, replacer));
+        let mut table_page = setup_page(&bpm);
+
+        let metadata = TupleMetadata::new(false);
+        let tuple_size = (table_page.contiguous_free_space() - 2 * TUPLE_INFO_SIZE) / 2;
+        let first = table_page
+            .insert_tuple(&metadata, &Tuple::new(vec![1; tuple_size]))
+            .unwrap();
+        let second = table_page
+            .insert_tuple(&metadata, &Tuple::new(vec![2; tuple_size]))
+            .unwrap();
+
+        let tuple = Tuple::new(vec![3; tuple_size]);
+        assert!(matches!(
+            table_page.insert_tuple(&metadata, &tuple),
+            Err(Error::OutOfBounds)
+        ));
+
+        // the bytes of the deleted tuple are reclaimed to make room
+        delete(&mut table_page, &first);
+        let rid = table_page.insert_tuple(&metadata, &tuple).unwrap();
+        assert_eq!(first, rid);
+        assert_eq!(0, table_page.deleted_tuple_count());
+        assert_eq!(
+            &vec![2; tuple_size],
+            table_page.get_tuple(&second).unwrap().1.data()
+        );
+        assert_eq!(
+            &vec![3; tuple_size],
+            table_page.get_tuple(&rid).unwrap().1.data()
+        );
+    }
 }
@@ -1,6 +1,13 @@
-use crate::{page::INVALID_PAGE_ID, typedef::PageId};
+use std::fmt;
 
-#[derive(Clone, Debug, Hash)]
+use rustdb_error::errdata;
+
+use crate::{page::INVALID_PAGE_ID, typedef::PageId, Result};
+
+/// The size of a record id stored in a page, see [`RecordId::to_bytes`].
+pub(crate) const RECORD_ID_SIZE: usize = 10;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct RecordId {
     page_id: PageId,
     slot_id: u16,
@@ -19,10 +26,6 @@ impl RecordId {
         }
     }
 
-    pub fn to_string(&self) -> String {
-        format!("{}:{}", self.page_id, self.slot_id)
-    }
-
     pub fn page_id(&self) -> PageId {
         self.page_id
     }
@@ -30,30 +33,47 @@ impl RecordId {
     pub fn slot_id(&self) -> u16 {
         self.slot_id
     }
-}
 
-impl PartialEq<Self> for RecordId {
-    fn eq(&self, other: &Self) -> bool {
-        self.page_id == other.page_id && self.slot_id == other.slot_id
+    /// Encodes the record id to store it in a page, as the little-endian page id followed by
+    /// the little-endian slot id.
+    pub(crate) fn to_bytes(&self) -> [u8; RECORD_ID_SIZE] {
+        let mut bytes = [0; RECORD_ID_SIZE];
+        bytes[..8].copy_from_slice(&(self.page_id as u64).to_le_bytes());
+        bytes[8..].copy_from_slice(&self.slot_id.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes a record id encoded by [`RecordId::to_bytes`].
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<RecordId> {
+        if bytes.len() != RECORD_ID_SIZE {
+            return errdata!("Invalid record id of {} bytes.", bytes.len());
+        }
+        let page_id = u64::from_le_bytes(bytes[..8].try_into()?) as PageId;
+        let slot_id = u16::from_le_bytes(bytes[8..].try_into()?);
+        Ok(RecordId::new(page_id, slot_id))
     }
 }
 
-impl Eq for RecordId {} // implement Eq trait for RecordId, uses PartialEq
+impl fmt::Display for RecordId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.page_id, self.slot_id)
+    }
+}
 
 impl Ord for RecordId {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         if self.page_id == other.page_id {
             if self.slot_id < other.slot_id {
-                return std::cmp::Ordering::Less;
+                std::cmp::Ordering::Less
             } else if self.slot_id > other.slot_id {
-                return std::cmp::Ordering::Greater;
+                std::cmp::Ordering::Greater
             } else {
-                return std::cmp::Ordering::Equal;
+                std::cmp::Ordering::Equal
             }
         } else if self.page_id < other.page_id {
-            return std::cmp::Ordering::Less;
+            std::cmp::Ordering::Less
         } else {
-            return std::cmp::Ordering::Greater;
+            std::cmp::Ordering::Greater
         }
     }
 }
@@ -90,6 +110,14 @@ mod record_id_tests {
         assert_eq!("1:1", rid.to_string());
     }
 
+    #[test]
+    fn test_bytes_round_trip() -> Result<()> {
+        let rid = RecordId::new(123456789, 42);
+        assert_eq!(rid, RecordId::from_bytes(&rid.to_bytes())?);
+        assert!(RecordId::from_bytes(&rid.to_bytes()[1..]).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_equals() {
         let rid1 = RecordId::new(1, 1);
@@ -0,0 +1,455 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::typedef::{FrameId, PageId};
+
+use super::ghost_list::GhostList;
+use super::replacer::{AccessType, Replacer};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ArcList {
+    /// Resident pages that were accessed once since they were last brought in.
+    Recent,
+    /// Resident pages that were accessed at least twice, or were remembered in a ghost list.
+    Frequent,
+}
+
+struct ArcNode {
+    page_id: PageId,
+    list: ArcList,
+    is_evictable: bool,
+    last_accessed_timestamp: u64,
+}
+
+/// The ARC (adaptive replacement cache) policy by Megiddo and Modha. Resident pages are split
+/// into a recency list of pages seen once and a frequency list of pages seen more than once, and
+/// the ids of pages evicted from either list are remembered in a ghost list of their own. A
+/// re-accessed page in the recent ghost list means the recency list was too short and grows its
+/// target size, a hit in the frequent ghost list shrinks it. Eviction takes the least recently
+/// used evictable page from the recency list while it is above its target size, and from the
+/// frequency list otherwise. One-off scans thus only ever displace other pages seen once.
+pub(crate) struct ArcReplacer {
+    capacity: usize,
+    node_store: HashMap<FrameId, ArcNode>,
+    /// Evictable frames of each list keyed by their last access timestamp.
+    recent_evictable: BTreeMap<u64, FrameId>,
+    frequent_evictable: BTreeMap<u64, FrameId>,
+    /// Number of resident pages in the recency list, including pinned ones.
+    recent_count: usize,
+    recent_ghosts: GhostList,
+    frequent_ghosts: GhostList,
+    /// Nodes of evicted frames until the frames are reused, so that a frame can be reinserted
+    /// where it was evicted from.
+    evicted: HashMap<FrameId, ArcNode>,
+    /// Target size of the recency list, between 0 and `capacity`.
+    recent_target: usize,
+    current_timestamp: u64,
+}
+
+impl ArcReplacer {
+    /// Creates a replacer for a pool of `capacity` frames.
+    pub(crate) fn new(capacity: usize) -> Self {
+        ArcReplacer {
+            capacity,
+            node_store: HashMap::new(),
+            recent_evictable: BTreeMap::new(),
+            frequent_evictable: BTreeMap::new(),
+            recent_count: 0,
+            recent_ghosts: GhostList::default(),
+            frequent_ghosts: GhostList::default(),
+            evicted: HashMap::new(),
+            recent_target: 0,
+            current_timestamp: 0,
+        }
+    }
+
+    fn current_timestamp(&mut self) -> u64 {
+        let old_timestamp = self.current_timestamp;
+        self.current_timestamp += 1;
+        old_timestamp
+    }
+
+    fn evictable_list(&mut self, list: ArcList) -> &mut BTreeMap<u64, FrameId> {
+        match list {
+            ArcList::Recent => &mut self.recent_evictable,
+            ArcList::Frequent => &mut self.frequent_evictable,
+        }
+    }
+
+    /// Adapts the recency target to a page found in a ghost list, and returns the list the
+    /// page is brought back into.
+    fn adapt(&mut self, page_id: &PageId) -> ArcList {
+        let recent_ghosts = self.recent_ghosts.len().max(1);
+        let frequent_ghosts = self.frequent_ghosts.len().max(1);
+
+        if self.recent_ghosts.remove(page_id) {
+            let delta = (frequent_ghosts / recent_ghosts).max(1);
+            self.recent_target = (self.recent_target + delta).min(self.capacity);
+            ArcList::Frequent
+        } else if self.frequent_ghosts.remove(page_id) {
+            let delta = (recent_ghosts / frequent_ghosts).max(1);
+            self.recent_target = self.recent_target.saturating_sub(delta);
+            ArcList::Frequent
+        } else {
+            ArcList::Recent
+        }
+    }
+
+    /// Bounds the ghost lists, so that the recency list and its ghosts together track at most
+    /// `capacity` pages, and all lists together at most twice that.
+    fn trim_ghosts(&mut self) {
+        while self.recent_ghosts.len() > 0
+            && self.recent_count + self.recent_ghosts.len() > self.capacity
+        {
+            self.recent_ghosts.pop_oldest();
+        }
+        while self.frequent_ghosts.len() > 0
+            && self.node_store.len() + self.recent_ghosts.len() + self.frequent_ghosts.len()
+                > 2 * self.capacity
+        {
+            self.frequent_ghosts.pop_oldest();
+        }
+    }
+}
+
+impl Replacer for ArcReplacer {
+    /// Marks a frame as evictable
+    fn unpin(&mut self, frame_id: FrameId) {
+        if let Some(node) = self.node_store.get_mut(&frame_id) {
+            if !node.is_evictable {
+                node.is_evictable = true;
+                let (list, timestamp) = (node.list, node.last_accessed_timestamp);
+                self.evictable_list(list).insert(timestamp, frame_id);
+            }
+        }
+    }
+
+    /// Marks a frame as not evictable (i.e., pinned).
+    fn pin(&mut self, frame_id: FrameId) {
+        if let Some(node) = self.node_store.get_mut(&frame_id) {
+            if node.is_evictable {
+                node.is_evictable = false;
+                let (list, timestamp) = (node.list, node.last_accessed_timestamp);
+                self.evictable_list(list).remove(&timestamp);
+            }
+        }
+    }
+
+    /// Records an access to the page held by the frame. A resident page that is accessed again
+    /// moves to the frequency list. A page that is brought in goes to the frequency list if it
+    /// is remembered in a ghost list, and to the recency list otherwise. Scans neither move
+    /// resident pages nor adapt the recency target, and always bring pages into the recency list.
+    fn record_access(&mut self, frame_id: FrameId, page_id: PageId, access_type: AccessType) {
+        let is_scan = access_type == AccessType::Scan;
+        if is_scan && self.node_store.contains_key(&frame_id) {
+            return;
+        }
+
+        let timestamp = self.current_timestamp();
+
+        if let Some(node) = self.node_store.get_mut(&frame_id) {
+            let (list, old_timestamp) = (node.list, node.last_accessed_timestamp);
+            node.list = ArcList::Frequent;
+            node.last_accessed_timestamp = timestamp;
+            if node.is_evictable {
+                self.evictable_list(list).remove(&old_timestamp);
+                self.frequent_evictable.insert(timestamp, frame_id);
+            }
+            if list == ArcList::Recent {
+                self.recent_count -= 1;
+            }
+            return;
+        }
+
+        self.evicted.remove(&frame_id);
+        let list = if is_scan {
+            self.recent_ghosts.remove(&page_id);
+            self.frequent_ghosts.remove(&page_id);
+            ArcList::Recent
+        } else {
+            self.adapt(&page_id)
+        };
+        self.node_store.insert(
+            frame_id,
+            ArcNode {
+                page_id,
+                list,
+                is_evictable: true,
+                last_accessed_timestamp: timestamp,
+            },
+        );
+        self.evictable_list(list).insert(timestamp, frame_id);
+        if list == ArcList::Recent {
+            self.recent_count += 1;
+        }
+        self.trim_ghosts();
+    }
+
+    /// Evicts the least recently used evictable frame of the recency list if the list is above
+    /// its target size, and of the frequency list otherwise. The evicted page is remembered in
+    /// the ghost list of its list.
+    fn evict(&mut self) -> Option<FrameId> {
+        let prefer_recent = self.recent_count > self.recent_target;
+        let (first, second) = if prefer_recent {
+            (&mut self.recent_evictable, &mut self.frequent_evictable)
+        } else {
+            (&mut self.frequent_evictable, &mut self.recent_evictable)
+        };
+        let (_, frame_id) = first.pop_first().or_else(|| second.pop_first())?;
+
+        let node = self.node_store.remove(&frame_id).unwrap();
+        let timestamp = self.current_timestamp();
+        match node.list {
+            ArcList::Recent => {
+                self.recent_count -= 1;
+                self.recent_ghosts.push(node.page_id, timestamp);
+            }
+            ArcList::Frequent => self.frequent_ghosts.push(node.page_id, timestamp),
+        }
+        self.evicted.insert(frame_id, node);
+        self.trim_ghosts();
+
+        Some(frame_id)
+    }
+
+    /// Returns the number of evictable frames.
+    fn evictable_count(&self) -> usize {
+        self.recent_evictable.len() + self.frequent_evictable.len()
+    }
+
+    /// Removes a frame entirely, without remembering its page in a ghost list.
+    fn remove(&mut self, frame_id: FrameId) {
+        if let Some(node) = self.node_store.remove(&frame_id) {
+            if !node.is_evictable {
+                panic!("replacer remove should only be called on evictable frame");
+            }
+            self.evictable_list(node.list)
+                .remove(&node.last_accessed_timestamp);
+            if node.list == ArcList::Recent {
+                self.recent_count -= 1;
+            }
+        }
+    }
+
+    /// Puts the frame back into the list it was evicted from, at the position it was evicted
+    /// from, and forgets the ghost its eviction left behind. The recency target is unchanged.
+    fn reinsert(&mut self, frame_id: FrameId, page_id: PageId) {
+        let Some(node) = self
+            .evicted
+            .remove(&frame_id)
+            .filter(|node| node.page_id == page_id)
+        else {
+            // Scans bring pages in without adapting the recency target.
+            self.record_access(frame_id, page_id, AccessType::Scan);
+            return;
+        };
+
+        match node.list {
+            ArcList::Recent => {
+                self.recent_ghosts.remove(&page_id);
+                self.recent_count += 1;
+            }
+            ArcList::Frequent => {
+                self.frequent_ghosts.remove(&page_id);
+            }
+        }
+        self.evictable_list(node.list)
+            .insert(node.last_accessed_timestamp, frame_id);
+        self.node_store.insert(frame_id, node);
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evicted.retain(|&frame_id, _| frame_id < capacity);
+        self.recent_target = self.recent_target.min(capacity);
+        self.trim_ghosts();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_access() {
+        let mut arc = ArcReplacer::new(4);
+
+        arc.record_access(1, 1, AccessType::Unknown);
+        arc.record_access(2, 2, AccessType::Unknown);
+        arc.record_access(3, 3, AccessType::Unknown);
+
+        assert_eq!(arc.evictable_count(), 3);
+    }
+
+    #[test]
+    fn test_evict() {
+        let mut arc = ArcReplacer::new(4);
+
+        arc.record_access(1, 1, AccessType::Unknown);
+        arc.record_access(2, 2, AccessType::Unknown);
+        arc.record_access(3, 3, AccessType::Unknown);
+
+        assert_eq!(arc.evict(), Some(1));
+        assert_eq!(arc.evict(), Some(2));
+        assert_eq!(arc.evict(), Some(3));
+        assert_eq!(arc.evict(), None);
+    }
+
+    #[test]
+    fn test_pin() {
+        let mut arc = ArcReplacer::new(4);
+
+        arc.record_access(1, 1, AccessType::Unknown);
+        arc.record_access(2, 2, AccessType::Unknown);
+        arc.record_access(3, 3, AccessType::Unknown);
+
+        arc.pin(2);
+
+        assert_eq!(arc.evictable_count(), 2);
+        assert_eq!(arc.evict(), Some(1));
+        assert_eq!(arc.evict(), Some(3));
+        assert_eq!(arc.evict(), None);
+    }
+
+    #[test]
+    fn test_unpin() {
+        let mut arc = ArcReplacer::new(4);
+
+        arc.record_access(1, 1, AccessType::Unknown);
+        arc.record_access(2, 2, AccessType::Unknown);
+        arc.record_access(3, 3, AccessType::Unknown);
+
+        arc.pin(2);
+        assert_eq!(arc.evictable_count(), 2);
+
+        arc.unpin(2);
+        assert_eq!(arc.evictable_count(), 3);
+
+        assert_eq!(arc.evict(), Some(1));
+        assert_eq!(arc.evict(), Some(2));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut arc = ArcReplacer::new(4);
+
+        arc.record_access(1, 1, AccessType::Unknown);
+        arc.record_access(2, 2, AccessType::Unknown);
+        arc.record_access(3, 3, AccessType::Unknown);
+
+        arc.remove(2);
+
+        assert_eq!(arc.evictable_count(), 2);
+        assert_eq!(arc.evict(), Some(1));
+        assert_eq!(arc.evict(), Some(3));
+        assert_eq!(arc.evict(), None);
+        assert_eq!(arc.recent_ghosts.len(), 2);
+    }
+
+    #[test]
+    fn test_reinsert_does_not_adapt() {
+        let mut arc = ArcReplacer::new(4);
+
+        arc.record_access(1, 1, AccessType::Unknown);
+        arc.record_access(2, 2, AccessType::Unknown);
+        arc.record_access(3, 3, AccessType::Unknown);
+        arc.record_access(1, 1, AccessType::Unknown);
+
+        // 2 goes back to the front of the recency list, and its ghost is forgotten
+        assert_eq!(arc.evict(), Some(2));
+        arc.reinsert(2, 2);
+        assert_eq!(arc.recent_target, 0);
+        assert_eq!(arc.recent_ghosts.len(), 0);
+        assert_eq!(arc.evictable_count(), 3);
+        assert_eq!(arc.evict(), Some(2));
+
+        // 1 goes back to the frequency list
+        assert_eq!(arc.evict(), Some(3));
+        assert_eq!(arc.evict(), Some(1));
+        arc.reinsert(1, 1);
+        assert_eq!(arc.recent_target, 0);
+        assert_eq!(arc.frequent_ghosts.len(), 0);
+        assert_eq!(arc.recent_count, 0);
+        assert_eq!(arc.evict(), Some(1));
+    }
+
+    #[test]
+    fn test_record_access_multiple_times() {
+        let mut arc = ArcReplacer::new(4);
+
+        arc.record_access(1, 1, AccessType::Unknown);
+        arc.record_access(2, 2, AccessType::Unknown);
+        arc.record_access(3, 3, AccessType::Unknown);
+
+        // 1 moves to the frequency list, which is only evicted from once recency is exhausted
+        arc.record_access(1, 1, AccessType::Unknown);
+
+        assert_eq!(arc.evict(), Some(2));
+        assert_eq!(arc.evict(), Some(3));
+        assert_eq!(arc.evict(), Some(1));
+
+        assert_eq!(arc.evictable_count(), 0);
+    }
+
+    #[test]
+    fn test_scan_does_not_evict_frequent_pages() {
+        let mut arc = ArcReplacer::new(4);
+
+        // pages 100 and 101 are hot
+        for _ in 0..2 {
+            arc.record_access(0, 100, AccessType::Unknown);
+            arc.record_access(1, 101, AccessType::Unknown);
+        }
+
+        // a scan over pages 0.. keeps cycling through frames 2 and 3
+        arc.record_access(2, 0, AccessType::Unknown);
+        arc.record_access(3, 1, AccessType::Unknown);
+        for page_id in 2..10 {
+            let frame_id = arc.evict().unwrap();
+            assert!(frame_id == 2 || frame_id == 3);
+            arc.record_access(frame_id, page_id, AccessType::Unknown);
+        }
+    }
+
+    #[test]
+    fn test_ghost_hits_adapt_recency_target() {
+        let mut arc = ArcReplacer::new(2);
+
+        arc.record_access(0, 10, AccessType::Unknown);
+        arc.record_access(1, 11, AccessType::Unknown);
+
+        // page 10 is evicted from the recency list and remembered as a ghost
+        assert_eq!(arc.evict(), Some(0));
+        assert_eq!(arc.recent_target, 0);
+
+        // bringing it back means the recency list was too short
+        arc.record_access(0, 10, AccessType::Unknown);
+        assert_eq!(arc.recent_target, 1);
+        assert_eq!(arc.recent_ghosts.len(), 0);
+
+        // page 11 is now alone in the recency list at its target size, so the frequency list
+        // gives up page 10 instead
+        assert_eq!(arc.evict(), Some(0));
+        assert_eq!(arc.frequent_ghosts.len(), 1);
+
+        // bringing back page 10 from the frequent ghosts shrinks the recency target again
+        arc.record_access(0, 10, AccessType::Unknown);
+        assert_eq!(arc.recent_target, 0);
+    }
+
+    #[test]
+    fn test_scans_do_not_promote_or_adapt() {
+        let mut arc = ArcReplacer::new(2);
+
+        arc.record_access(0, 10, AccessType::Scan);
+        arc.record_access(1, 11, AccessType::Lookup);
+        arc.record_access(0, 10, AccessType::Scan);
+        assert_eq!(arc.recent_count, 2);
+
+        // page 10 is evicted first and scanned back in from the ghost list
+        assert_eq!(arc.evict(), Some(0));
+        arc.record_access(0, 10, AccessType::Scan);
+        assert_eq!(arc.recent_target, 0);
+        assert_eq!(arc.recent_ghosts.len(), 0);
+        assert_eq!(arc.recent_count, 2);
+    }
+}
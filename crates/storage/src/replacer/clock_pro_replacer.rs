@@ -0,0 +1,468 @@
+use crate::typedef::{FrameId, PageId};
+
+use super::ghost_list::GhostList;
+use super::replacer::{AccessType, Replacer};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Hot,
+    /// A cold page is in its test period until the hot hand passes it. A cold page that is
+    /// re-accessed during its test period is promoted to hot.
+    Cold {
+        in_test: bool,
+    },
+}
+
+#[derive(Clone, Copy)]
+struct ClockProEntry {
+    page_id: PageId,
+    status: Status,
+    is_evictable: bool,
+    referenced: bool,
+}
+
+/// The CLOCK-Pro policy. Frames are split into hot frames, which have shown a short reuse
+/// distance, and cold frames. The cold hand only evicts cold frames, so a large scan only ever
+/// cycles through the cold frames while the hot working set stays cached. The hot hand demotes
+/// hot frames that were not referenced since its last pass once there are too many hot frames,
+/// and ends the test periods of the cold frames it passes.
+///
+/// The number of frames reserved for cold pages adapts between 1 and `capacity - 1`. Cold pages
+/// evicted during their test period are remembered by page id until `capacity` later ones push
+/// them out, which ends their test period. A remembered page that is accessed again would have
+/// stayed cached with more cold frames, so the cold target grows and the page comes back hot.
+/// The cold target shrinks whenever the test period of a cold page ends without a re-access.
+pub(crate) struct ClockProReplacer {
+    entries: Vec<Option<ClockProEntry>>,
+    /// Cold pages evicted during their test period.
+    non_resident: GhostList,
+    hand_cold: usize,
+    hand_hot: usize,
+    hot_count: usize,
+    cold_target: usize,
+    evictable_count: usize,
+    eviction_count: u64,
+}
+
+impl ClockProReplacer {
+    /// Creates a replacer for frame ids in `0..capacity`.
+    pub(crate) fn new(capacity: usize) -> Self {
+        ClockProReplacer {
+            entries: vec![None; capacity],
+            non_resident: GhostList::default(),
+            hand_cold: 0,
+            hand_hot: 0,
+            hot_count: 0,
+            cold_target: 1,
+            evictable_count: 0,
+            eviction_count: 0,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn max_hot_count(&self) -> usize {
+        self.capacity().saturating_sub(self.cold_target)
+    }
+
+    fn adjust_cold_target(&mut self, increase: bool) {
+        let max_cold_target = self.capacity().saturating_sub(1).max(1);
+        self.cold_target = if increase {
+            (self.cold_target + 1).min(max_cold_target)
+        } else {
+            self.cold_target.saturating_sub(1).max(1)
+        };
+    }
+
+    /// Remembers a cold page evicted during its test period. Once more than `capacity` pages
+    /// are remembered, the test period of the oldest one ends without a re-access.
+    fn remember_non_resident(&mut self, page_id: PageId) {
+        self.non_resident.push(page_id, self.eviction_count);
+        self.eviction_count += 1;
+        while self.non_resident.len() > self.capacity() {
+            self.non_resident.pop_oldest();
+            self.adjust_cold_target(false);
+        }
+    }
+
+    /// Balances the hot frames after one turned hot, by demoting others if there are too many.
+    fn demote_excess_hot(&mut self) {
+        while self.hot_count > self.max_hot_count() && self.run_hand_hot(false) {}
+    }
+
+    /// Moves the hot hand until it demotes a hot frame, or until it went around the clock twice.
+    /// With `evictable_only`, only evictable hot frames are demoted. Returns whether a frame was
+    /// demoted.
+    fn run_hand_hot(&mut self, evictable_only: bool) -> bool {
+        for _ in 0..2 * self.capacity() {
+            let frame_id = self.hand_hot;
+            self.hand_hot = (self.hand_hot + 1) % self.capacity();
+
+            let Some(entry) = self.entries[frame_id].as_mut() else {
+                continue;
+            };
+            match entry.status {
+                Status::Hot if evictable_only && !entry.is_evictable => {}
+                Status::Hot if entry.referenced => entry.referenced = false,
+                Status::Hot => {
+                    entry.status = Status::Cold { in_test: false };
+                    self.hot_count -= 1;
+                    return true;
+                }
+                Status::Cold { in_test: true } if !entry.referenced => {
+                    entry.status = Status::Cold { in_test: false };
+                    self.adjust_cold_target(false);
+                }
+                Status::Cold { .. } => {}
+            }
+        }
+        false
+    }
+
+    /// Moves the cold hand over the clock once, and returns the first evictable cold frame that
+    /// was not referenced since the hand last passed it. Referenced cold frames in their test
+    /// period are promoted to hot, other referenced cold frames start a new test period.
+    fn run_hand_cold(&mut self) -> Option<FrameId> {
+        for _ in 0..self.capacity() {
+            let frame_id = self.hand_cold;
+            self.hand_cold = (self.hand_cold + 1) % self.capacity();
+
+            let Some(entry) = self.entries[frame_id].as_mut() else {
+                continue;
+            };
+            let Status::Cold { in_test } = entry.status else {
+                continue;
+            };
+            if !entry.is_evictable {
+                continue;
+            }
+
+            if !entry.referenced {
+                let page_id = entry.page_id;
+                self.entries[frame_id] = None;
+                self.evictable_count -= 1;
+                if in_test {
+                    self.remember_non_resident(page_id);
+                }
+                return Some(frame_id);
+            }
+
+            entry.referenced = false;
+            if in_test {
+                entry.status = Status::Hot;
+                self.hot_count += 1;
+                self.demote_excess_hot();
+            } else {
+                entry.status = Status::Cold { in_test: true };
+            }
+        }
+        None
+    }
+}
+
+impl Replacer for ClockProReplacer {
+    /// Marks a frame as evictable
+    fn unpin(&mut self, frame_id: FrameId) {
+        if let Some(entry) = self.entries[frame_id].as_mut() {
+            if !entry.is_evictable {
+                entry.is_evictable = true;
+                self.evictable_count += 1;
+            }
+        }
+    }
+
+    /// Marks a frame as not evictable (i.e., pinned).
+    fn pin(&mut self, frame_id: FrameId) {
+        if let Some(entry) = self.entries[frame_id].as_mut() {
+            if entry.is_evictable {
+                entry.is_evictable = false;
+                self.evictable_count -= 1;
+            }
+        }
+    }
+
+    /// Sets the reference bit of the frame, unless it is accessed by a scan.
+    /// If the frame_id is new, it is added as an evictable cold frame. Only frames that are not
+    /// brought in by a scan start in their test period, so a single re-access of a scanned frame
+    /// does not promote it to hot. A page that is brought back during the test period it was
+    /// evicted in grows the cold target and is added as a hot frame instead.
+    fn record_access(&mut self, frame_id: FrameId, page_id: PageId, access_type: AccessType) {
+        let is_scan = access_type == AccessType::Scan;
+        if let Some(entry) = self.entries[frame_id].as_mut() {
+            entry.referenced |= !is_scan;
+            return;
+        }
+
+        let in_test = self.non_resident.remove(&page_id) && !is_scan;
+        let status = if in_test {
+            self.adjust_cold_target(true);
+            Status::Hot
+        } else {
+            Status::Cold { in_test: !is_scan }
+        };
+        self.entries[frame_id] = Some(ClockProEntry {
+            page_id,
+            status,
+            is_evictable: true,
+            referenced: false,
+        });
+        self.evictable_count += 1;
+        if status == Status::Hot {
+            self.hot_count += 1;
+            self.demote_excess_hot();
+        }
+    }
+
+    /// Evicts a cold frame. If every evictable frame is hot, an evictable hot frame is demoted
+    /// first.
+    fn evict(&mut self) -> Option<FrameId> {
+        if self.evictable_count == 0 {
+            return None;
+        }
+
+        // The first pass clears the reference bits of the cold frames it does not evict, so the
+        // second one finds a victim unless every evictable frame is hot.
+        for _ in 0..2 {
+            if let Some(frame_id) = self.run_hand_cold() {
+                return Some(frame_id);
+            }
+        }
+
+        let demoted = self.run_hand_hot(true);
+        assert!(demoted, "An evictable frame must exist");
+        self.run_hand_cold()
+    }
+
+    /// Returns the number of evictable frames.
+    fn evictable_count(&self) -> usize {
+        self.evictable_count
+    }
+
+    /// Removes a frame from the clock entirely.
+    fn remove(&mut self, frame_id: FrameId) {
+        let Some(entry) = self.entries[frame_id].take() else {
+            return;
+        };
+        if !entry.is_evictable {
+            panic!("replacer remove should only be called on evictable frame");
+        }
+        if entry.status == Status::Hot {
+            self.hot_count -= 1;
+        }
+        self.evictable_count -= 1;
+    }
+
+    /// Puts back an evicted frame as a cold frame, still in the test period it was evicted in.
+    /// The page is not treated as re-accessed, so the cold target is unchanged.
+    fn reinsert(&mut self, frame_id: FrameId, page_id: PageId) {
+        if self.entries[frame_id].is_some() {
+            return;
+        }
+        let in_test = self.non_resident.remove(&page_id);
+        self.entries[frame_id] = Some(ClockProEntry {
+            page_id,
+            status: Status::Cold { in_test },
+            is_evictable: true,
+            referenced: false,
+        });
+        self.evictable_count += 1;
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.entries.resize(capacity, None);
+        if self.hand_cold >= capacity {
+            self.hand_cold = 0;
+        }
+        if self.hand_hot >= capacity {
+            self.hand_hot = 0;
+        }
+        self.cold_target = self.cold_target.min(capacity.saturating_sub(1).max(1));
+        while self.non_resident.len() > capacity {
+            self.non_resident.pop_oldest();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_access() {
+        let mut clock_pro = ClockProReplacer::new(4);
+
+        clock_pro.record_access(1, 1, AccessType::Unknown);
+        clock_pro.record_access(2, 2, AccessType::Unknown);
+        clock_pro.record_access(3, 3, AccessType::Unknown);
+
+        assert_eq!(clock_pro.evictable_count(), 3);
+    }
+
+    #[test]
+    fn test_evict() {
+        let mut clock_pro = ClockProReplacer::new(4);
+
+        clock_pro.record_access(1, 1, AccessType::Unknown);
+        clock_pro.record_access(2, 2, AccessType::Unknown);
+        clock_pro.record_access(3, 3, AccessType::Unknown);
+
+        assert_eq!(clock_pro.evict(), Some(1));
+        assert_eq!(clock_pro.evict(), Some(2));
+        assert_eq!(clock_pro.evict(), Some(3));
+        assert_eq!(clock_pro.evict(), None);
+    }
+
+    #[test]
+    fn test_pin() {
+        let mut clock_pro = ClockProReplacer::new(4);
+
+        clock_pro.record_access(1, 1, AccessType::Unknown);
+        clock_pro.record_access(2, 2, AccessType::Unknown);
+        clock_pro.record_access(3, 3, AccessType::Unknown);
+
+        clock_pro.pin(2);
+
+        assert_eq!(clock_pro.evictable_count(), 2);
+        assert_eq!(clock_pro.evict(), Some(1));
+        assert_eq!(clock_pro.evict(), Some(3));
+        assert_eq!(clock_pro.evict(), None);
+    }
+
+    #[test]
+    fn test_unpin() {
+        let mut clock_pro = ClockProReplacer::new(4);
+
+        clock_pro.record_access(1, 1, AccessType::Unknown);
+        clock_pro.record_access(2, 2, AccessType::Unknown);
+        clock_pro.record_access(3, 3, AccessType::Unknown);
+
+        clock_pro.pin(2);
+        assert_eq!(clock_pro.evictable_count(), 2);
+
+        clock_pro.unpin(2);
+        assert_eq!(clock_pro.evictable_count(), 3);
+
+        assert_eq!(clock_pro.evict(), Some(1));
+        assert_eq!(clock_pro.evict(), Some(2));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut clock_pro = ClockProReplacer::new(4);
+
+        clock_pro.record_access(1, 1, AccessType::Unknown);
+        clock_pro.record_access(2, 2, AccessType::Unknown);
+        clock_pro.record_access(3, 3, AccessType::Unknown);
+
+        clock_pro.remove(2);
+
+        assert_eq!(clock_pro.evictable_count(), 2);
+        assert_eq!(clock_pro.evict(), Some(1));
+        assert_eq!(clock_pro.evict(), Some(3));
+        assert_eq!(clock_pro.evict(), None);
+    }
+
+    #[test]
+    fn test_scan_does_not_evict_hot_frames() {
+        let mut clock_pro = ClockProReplacer::new(4);
+
+        for frame_id in 0..4 {
+            clock_pro.record_access(frame_id, frame_id, AccessType::Unknown);
+        }
+        // 0 and 1 are re-accessed during their test period and become hot
+        clock_pro.record_access(0, 0, AccessType::Unknown);
+        clock_pro.record_access(1, 1, AccessType::Unknown);
+
+        // a scan keeps cycling through the cold frames only
+        for page_id in 10..20 {
+            let frame_id = clock_pro.evict().unwrap();
+            assert!(frame_id == 2 || frame_id == 3);
+            clock_pro.record_access(frame_id, page_id, AccessType::Unknown);
+        }
+        assert_eq!(clock_pro.hot_count, 2);
+    }
+
+    #[test]
+    fn test_hot_frames_are_demoted_when_needed() {
+        let mut clock_pro = ClockProReplacer::new(2);
+
+        clock_pro.record_access(0, 0, AccessType::Unknown);
+        clock_pro.record_access(1, 1, AccessType::Unknown);
+        clock_pro.record_access(0, 0, AccessType::Unknown);
+        clock_pro.record_access(1, 1, AccessType::Unknown);
+
+        // Both frames would turn hot, but one frame is reserved for cold pages, so 0 is demoted
+        // again and evicted
+        assert_eq!(clock_pro.evict(), Some(0));
+        assert_eq!(clock_pro.hot_count, 1);
+
+        // Only a hot frame is left, so it is demoted before it can be evicted
+        assert_eq!(clock_pro.evict(), Some(1));
+        assert_eq!(clock_pro.hot_count, 0);
+        assert_eq!(clock_pro.evict(), None);
+    }
+
+    #[test]
+    fn test_cold_target_adapts() {
+        let mut clock_pro = ClockProReplacer::new(4);
+
+        for page_id in 0..4 {
+            clock_pro.record_access(page_id, page_id, AccessType::Unknown);
+        }
+        assert_eq!(clock_pro.cold_target, 1);
+
+        // pages brought back during the test period they were evicted in grow the cold target
+        for page_id in 0..2 {
+            let frame_id = clock_pro.evict().unwrap();
+            assert_eq!(frame_id, page_id);
+            clock_pro.record_access(frame_id, page_id, AccessType::Unknown);
+        }
+        assert_eq!(clock_pro.cold_target, 3);
+        assert_eq!(clock_pro.hot_count, 1);
+
+        // pages that are not brought back end their test period and shrink it again
+        for page_id in 10..20 {
+            let frame_id = clock_pro.evict().unwrap();
+            clock_pro.record_access(frame_id, page_id, AccessType::Unknown);
+        }
+        assert_eq!(clock_pro.cold_target, 1);
+        assert!(clock_pro.non_resident.len() <= 4);
+    }
+
+    #[test]
+    fn test_reinsert_does_not_adapt() {
+        let mut clock_pro = ClockProReplacer::new(4);
+
+        clock_pro.record_access(0, 0, AccessType::Unknown);
+        clock_pro.record_access(1, 1, AccessType::Unknown);
+
+        assert_eq!(clock_pro.evict(), Some(0));
+        clock_pro.reinsert(0, 0);
+        assert_eq!(clock_pro.cold_target, 1);
+        assert_eq!(clock_pro.hot_count, 0);
+        assert_eq!(clock_pro.non_resident.len(), 0);
+        assert_eq!(clock_pro.evictable_count(), 2);
+    }
+
+    #[test]
+    fn test_scanned_frames_are_not_promoted() {
+        let mut clock_pro = ClockProReplacer::new(4);
+
+        clock_pro.record_access(0, 0, AccessType::Lookup);
+        clock_pro.record_access(1, 1, AccessType::Scan);
+        clock_pro.record_access(0, 0, AccessType::Lookup);
+        clock_pro.record_access(1, 1, AccessType::Scan);
+
+        // 0 is promoted, 1 was only scanned and is evicted
+        assert_eq!(clock_pro.evict(), Some(1));
+        assert_eq!(clock_pro.hot_count, 1);
+
+        // a lookup of a scanned frame only starts its test period
+        clock_pro.record_access(1, 1, AccessType::Scan);
+        clock_pro.record_access(1, 1, AccessType::Lookup);
+        assert_eq!(clock_pro.evict(), Some(1));
+        assert_eq!(clock_pro.hot_count, 1);
+    }
+}
@@ -0,0 +1,39 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::typedef::PageId;
+
+/// The ids of recently evicted pages, in eviction order.
+#[derive(Default)]
+pub(super) struct GhostList {
+    order: BTreeMap<u64, PageId>,
+    timestamps: HashMap<PageId, u64>,
+}
+
+impl GhostList {
+    pub(super) fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub(super) fn push(&mut self, page_id: PageId, timestamp: u64) {
+        self.remove(&page_id);
+        self.order.insert(timestamp, page_id);
+        self.timestamps.insert(page_id, timestamp);
+    }
+
+    /// Removes the page and returns whether it was in the list.
+    pub(super) fn remove(&mut self, page_id: &PageId) -> bool {
+        match self.timestamps.remove(page_id) {
+            Some(timestamp) => {
+                self.order.remove(&timestamp);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(super) fn pop_oldest(&mut self) {
+        if let Some((_, page_id)) = self.order.pop_first() {
+            self.timestamps.remove(&page_id);
+        }
+    }
+}
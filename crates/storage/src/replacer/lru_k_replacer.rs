@@ -1,86 +1,313 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 
-use crate::typedef::FrameId;
+use crate::typedef::{FrameId, PageId};
 
-use super::replacer::Replacer;
+use super::replacer::{AccessType, Replacer};
 
 struct LrukNode {
-    frame_id: FrameId,
     is_evictable: bool,
+    /// Timestamps of the last `k` accesses, oldest first.
     history: VecDeque<u64>,
-    k: usize,
 }
 
-fn max_timestamp() -> u64 {
-    u64::max_value()
+/// Orders frames by eviction priority: frames with fewer than `k` accesses, i.e. an infinite
+/// backward k-distance, come first, and within each group the frame whose oldest tracked access
+/// is furthest in the past. For frames with `k` accesses that access is the k-th most recent one,
+/// so the order matches the backward k-distance. Timestamps are unique, so keys are too.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct EvictionKey {
+    has_k_accesses: bool,
+    oldest_timestamp: u64,
 }
 
 impl LrukNode {
-    fn get_backwards_k_distance(&self, current_timestamp: u64) -> u64 {
-        if self.has_infinite_backwards_k_distance() {
-            return max_timestamp();
+    fn new(k: usize) -> Self {
+        LrukNode {
+            is_evictable: true,
+            history: VecDeque::with_capacity(k),
         }
-        current_timestamp - self.get_kth_most_recent_timestamp()
     }
 
-    fn has_infinite_backwards_k_distance(&self) -> bool {
-        self.history.len() < self.k
+    fn record_access(&mut self, timestamp: u64, k: usize) {
+        if self.history.len() == k {
+            self.history.pop_front();
+        }
+        self.history.push_back(timestamp);
     }
 
-    fn get_kth_most_recent_timestamp(&self) -> u64 {
-        let number_of_accesses = self.history.len();
-        if number_of_accesses < self.k {
-            panic!("Node has {number_of_accesses} < `k` accesses in its history.");
+    fn eviction_key(&self, k: usize) -> EvictionKey {
+        EvictionKey {
+            has_k_accesses: self.history.len() == k,
+            oldest_timestamp: *self
+                .history
+                .front()
+                .expect("Node should have at least one access"),
         }
-        *self.history.front().unwrap()
     }
 }
 
+/// Evicts the frame with the largest backward k-distance, i.e. the frame whose k-th most recent
+/// access is furthest in the past. Frames with fewer than `k` accesses have an infinite distance,
+/// and among those the frame with the earliest recorded access is evicted first.
 pub(crate) struct LrukReplacer {
     node_store: HashMap<FrameId, LrukNode>,
-    evictable_size: usize, // Tracks evictable nodes
+    /// Evictable frames in eviction order, so the first entry is always the victim.
+    evictable: BTreeMap<EvictionKey, FrameId>,
     current_timestamp: u64,
+    k: usize,
 }
 
 impl LrukReplacer {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(k: usize) -> Self {
+        assert!(k > 0, "LRU-K replacer needs k of at least 1");
         LrukReplacer {
             node_store: HashMap::new(),
-            evictable_size: 0,
+            evictable: BTreeMap::new(),
             current_timestamp: 0,
+            k,
         }
     }
 
     fn current_timestamp(&mut self) -> u64 {
         let old_timestamp = self.current_timestamp;
         self.current_timestamp += 1;
-        return old_timestamp;
+        old_timestamp
     }
 }
 
 impl Replacer for LrukReplacer {
+    /// Marks a frame as evictable
     fn unpin(&mut self, frame_id: FrameId) {
-        todo!()
+        if let Some(node) = self.node_store.get_mut(&frame_id) {
+            if !node.is_evictable {
+                node.is_evictable = true;
+                self.evictable.insert(node.eviction_key(self.k), frame_id);
+            }
+        }
     }
 
+    /// Marks a frame as not evictable (i.e., pinned).
     fn pin(&mut self, frame_id: FrameId) {
-        todo!()
+        if let Some(node) = self.node_store.get_mut(&frame_id) {
+            if node.is_evictable {
+                node.is_evictable = false;
+                self.evictable.remove(&node.eviction_key(self.k));
+            }
+        }
     }
 
-    fn record_access(&mut self, frame_id: FrameId) {
-        todo!()
+    /// Records an access at the current timestamp.
+    /// If the frame_id is new, create a new evictable node.
+    /// Scans are not added to the history of frames that are already tracked.
+    fn record_access(&mut self, frame_id: FrameId, _page_id: PageId, access_type: AccessType) {
+        if access_type == AccessType::Scan && self.node_store.contains_key(&frame_id) {
+            return;
+        }
+        let timestamp = self.current_timestamp();
+        let k = self.k;
+        let node = self
+            .node_store
+            .entry(frame_id)
+            .or_insert_with(|| LrukNode::new(k));
+        if node.is_evictable && !node.history.is_empty() {
+            self.evictable.remove(&node.eviction_key(k));
+        }
+        node.record_access(timestamp, k);
+        if node.is_evictable {
+            self.evictable.insert(node.eviction_key(k), frame_id);
+        }
     }
 
+    /// Evicts the evictable frame with the largest backward k-distance.
     fn evict(&mut self) -> Option<FrameId> {
-        // self.node_store.remove(frame_id);
-        todo!()
+        let (_, frame_id) = self.evictable.pop_first()?;
+        self.node_store.remove(&frame_id);
+        Some(frame_id)
     }
 
+    /// Returns the number of evictable frames.
     fn evictable_count(&self) -> usize {
-        todo!()
+        self.evictable.len()
     }
 
+    /// Removes a frame and its access history entirely.
     fn remove(&mut self, frame_id: FrameId) {
-        todo!()
+        if let Some(node) = self.node_store.remove(&frame_id) {
+            if node.is_evictable {
+                self.evictable.remove(&node.eviction_key(self.k));
+            } else {
+                panic!("replacer remove should only be called on evictable frame");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_access() {
+        let mut lru_k = LrukReplacer::new(2);
+
+        lru_k.record_access(1, 1, AccessType::Unknown);
+        lru_k.record_access(2, 2, AccessType::Unknown);
+        lru_k.record_access(3, 3, AccessType::Unknown);
+
+        assert_eq!(lru_k.evictable_count(), 3);
+    }
+
+    #[test]
+    fn test_evict() {
+        let mut lru_k = LrukReplacer::new(2);
+
+        lru_k.record_access(1, 1, AccessType::Unknown);
+        lru_k.record_access(2, 2, AccessType::Unknown);
+        lru_k.record_access(3, 3, AccessType::Unknown);
+
+        // All frames have +inf k-distance, so the earliest accessed goes first
+        assert_eq!(lru_k.evict(), Some(1));
+        assert_eq!(lru_k.evict(), Some(2));
+        assert_eq!(lru_k.evict(), Some(3));
+        assert_eq!(lru_k.evict(), None);
+    }
+
+    #[test]
+    fn test_pin() {
+        let mut lru_k = LrukReplacer::new(2);
+
+        lru_k.record_access(1, 1, AccessType::Unknown);
+        lru_k.record_access(2, 2, AccessType::Unknown);
+        lru_k.record_access(3, 3, AccessType::Unknown);
+
+        lru_k.pin(2);
+
+        assert_eq!(lru_k.evictable_count(), 2);
+        assert_eq!(lru_k.evict(), Some(1));
+        assert_eq!(lru_k.evict(), Some(3));
+        assert_eq!(lru_k.evict(), None);
+    }
+
+    #[test]
+    fn test_unpin() {
+        let mut lru_k = LrukReplacer::new(2);
+
+        lru_k.record_access(1, 1, AccessType::Unknown);
+        lru_k.record_access(2, 2, AccessType::Unknown);
+        lru_k.record_access(3, 3, AccessType::Unknown);
+
+        lru_k.pin(2);
+        assert_eq!(lru_k.evictable_count(), 2);
+
+        lru_k.unpin(2);
+        assert_eq!(lru_k.evictable_count(), 3);
+
+        assert_eq!(lru_k.evict(), Some(1));
+        assert_eq!(lru_k.evict(), Some(2));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut lru_k = LrukReplacer::new(2);
+
+        lru_k.record_access(1, 1, AccessType::Unknown);
+        lru_k.record_access(2, 2, AccessType::Unknown);
+        lru_k.record_access(3, 3, AccessType::Unknown);
+
+        lru_k.remove(2);
+
+        assert_eq!(lru_k.evictable_count(), 2);
+        assert_eq!(lru_k.evict(), Some(1));
+        assert_eq!(lru_k.evict(), Some(3));
+        assert_eq!(lru_k.evict(), None);
+    }
+
+    #[test]
+    fn test_record_access_multiple_times() {
+        let mut lru_k = LrukReplacer::new(2);
+
+        lru_k.record_access(1, 1, AccessType::Unknown); // t0
+        lru_k.record_access(2, 2, AccessType::Unknown); // t1
+        lru_k.record_access(3, 3, AccessType::Unknown); // t2
+        lru_k.record_access(2, 2, AccessType::Unknown); // t3
+        lru_k.record_access(1, 1, AccessType::Unknown); // t4
+
+        // 3 has fewer than k accesses, so its k-distance is +inf
+        assert_eq!(lru_k.evict(), Some(3));
+        // 1's second most recent access (t0) is older than 2's (t1)
+        assert_eq!(lru_k.evict(), Some(1));
+        assert_eq!(lru_k.evict(), Some(2));
+
+        assert_eq!(lru_k.evictable_count(), 0);
+    }
+
+    #[test]
+    fn test_accesses_while_pinned_count() {
+        let mut lru_k = LrukReplacer::new(2);
+
+        lru_k.record_access(1, 1, AccessType::Unknown);
+        lru_k.record_access(2, 2, AccessType::Unknown);
+        lru_k.record_access(3, 3, AccessType::Unknown);
+
+        lru_k.pin(1);
+        lru_k.record_access(1, 1, AccessType::Unknown);
+        lru_k.unpin(1);
+        lru_k.record_access(2, 2, AccessType::Unknown);
+
+        // 3 still has an infinite k-distance, 1 reached its k accesses first
+        assert_eq!(lru_k.evict(), Some(3));
+        assert_eq!(lru_k.evict(), Some(1));
+        assert_eq!(lru_k.evict(), Some(2));
+        assert_eq!(lru_k.evict(), None);
+    }
+
+    #[test]
+    fn test_only_last_k_accesses_count() {
+        let mut lru_k = LrukReplacer::new(2);
+
+        lru_k.record_access(1, 1, AccessType::Unknown); // t0
+        lru_k.record_access(1, 1, AccessType::Unknown); // t1
+        lru_k.record_access(2, 2, AccessType::Unknown); // t2
+        lru_k.record_access(2, 2, AccessType::Unknown); // t3
+        lru_k.record_access(1, 1, AccessType::Unknown); // t4
+
+        // 1's history is now [t1, t4], which is still older than 2's [t2, t3]
+        assert_eq!(lru_k.evict(), Some(1));
+
+        lru_k.record_access(3, 3, AccessType::Unknown); // t5
+        lru_k.record_access(3, 3, AccessType::Unknown); // t6
+        lru_k.record_access(2, 2, AccessType::Unknown); // t7
+
+        // 2's history is [t3, t7], 3's is [t5, t6]
+        assert_eq!(lru_k.evict(), Some(2));
+        assert_eq!(lru_k.evict(), Some(3));
+    }
+
+    #[test]
+    fn test_k_of_one_behaves_like_lru() {
+        let mut lru_k = LrukReplacer::new(1);
+
+        lru_k.record_access(1, 1, AccessType::Unknown);
+        lru_k.record_access(2, 2, AccessType::Unknown);
+        lru_k.record_access(3, 3, AccessType::Unknown);
+        lru_k.record_access(1, 1, AccessType::Unknown);
+
+        assert_eq!(lru_k.evict(), Some(2));
+        assert_eq!(lru_k.evict(), Some(3));
+        assert_eq!(lru_k.evict(), Some(1));
+    }
+
+    #[test]
+    fn test_scans_do_not_add_history() {
+        let mut lru_k = LrukReplacer::new(2);
+
+        lru_k.record_access(1, 1, AccessType::Lookup);
+        lru_k.record_access(2, 2, AccessType::Lookup);
+        lru_k.record_access(2, 2, AccessType::Lookup);
+        lru_k.record_access(1, 1, AccessType::Scan);
+
+        // 1 still has a single access and thus an infinite k-distance
+        assert_eq!(lru_k.evict(), Some(1));
+        assert_eq!(lru_k.evict(), Some(2));
     }
 }
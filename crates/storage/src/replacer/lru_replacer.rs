@@ -1,53 +1,57 @@
-use crate::typedef::FrameId;
-use std::collections::HashMap;
+use crate::typedef::{FrameId, PageId};
+use std::collections::{BTreeMap, HashMap};
 
-use super::replacer::Replacer;
+use super::replacer::{AccessType, Replacer};
+
+/// Timestamps of frames brought in by a scan count up from 0, and all other timestamps count up
+/// from here. Frames that were only ever scanned are thus evicted before any other frame.
+const FIRST_TIMESTAMP: u64 = 1 << 63;
 
 struct LruNode {
-    frame_id: FrameId,
     is_evictable: bool,
     last_accessed_timestamp: u64,
 }
 
+/// Evicts the least recently used evictable frame. Evictable frames are additionally indexed by
+/// their last access timestamp, so that every operation is O(log n) in the number of frames.
 pub(crate) struct LruReplacer {
     node_store: HashMap<FrameId, LruNode>,
-    evictable_count: usize, // Tracks evictable nodes
+    /// Evictable frames keyed by their last access timestamp. Timestamps are unique, so the
+    /// first entry is always the least recently used frame.
+    evictable: BTreeMap<u64, FrameId>,
     current_timestamp: u64,
+    scan_timestamp: u64,
 }
 
 impl LruReplacer {
     pub(crate) fn new() -> Self {
         LruReplacer {
             node_store: HashMap::new(),
-            evictable_count: 0,
-            current_timestamp: 0,
+            evictable: BTreeMap::new(),
+            current_timestamp: FIRST_TIMESTAMP,
+            scan_timestamp: 0,
         }
     }
 
     fn current_timestamp(&mut self) -> u64 {
         let old_timestamp = self.current_timestamp;
         self.current_timestamp += 1;
-        return old_timestamp;
+        old_timestamp
+    }
+
+    fn scan_timestamp(&mut self) -> u64 {
+        let old_timestamp = self.scan_timestamp;
+        self.scan_timestamp += 1;
+        old_timestamp
     }
 }
 
 impl Replacer for LruReplacer {
     /// Evicts the least recently used evictable frame.
     fn evict(&mut self) -> Option<FrameId> {
-        let lru_frame = self
-            .node_store
-            .values()
-            .filter(|node| node.is_evictable) // Only consider evictable frames
-            .min_by_key(|node| node.last_accessed_timestamp) // Find the smallest timestamp
-            .map(|node| node.frame_id);
-
-        if let Some(frame_id) = lru_frame {
-            self.node_store.remove(&frame_id);
-            self.evictable_count -= 1;
-            return Some(frame_id);
-        }
-
-        None
+        let (_, frame_id) = self.evictable.pop_first()?;
+        self.node_store.remove(&frame_id);
+        Some(frame_id)
     }
 
     /// Marks a frame as not evictable (i.e., pinned).
@@ -55,7 +59,7 @@ impl Replacer for LruReplacer {
         if let Some(node) = self.node_store.get_mut(&frame_id) {
             if node.is_evictable {
                 node.is_evictable = false;
-                self.evictable_count -= 1;
+                self.evictable.remove(&node.last_accessed_timestamp);
             }
         }
     }
@@ -65,28 +69,39 @@ impl Replacer for LruReplacer {
         if let Some(node) = self.node_store.get_mut(&frame_id) {
             if !node.is_evictable {
                 node.is_evictable = true;
-                self.evictable_count += 1;
+                self.evictable
+                    .insert(node.last_accessed_timestamp, frame_id);
             }
         }
     }
 
     /// Records an access and updates the timestamp.
-    /// If the frame_id is new, create a new node.
-    fn record_access(&mut self, frame_id: FrameId) {
-        let new_timestamp = self.current_timestamp();
+    /// If the frame_id is new, create a new evictable node.
+    /// Scans do not refresh frames that are already tracked, and new frames brought in by a scan
+    /// are placed before all other frames.
+    fn record_access(&mut self, frame_id: FrameId, _page_id: PageId, access_type: AccessType) {
+        let is_tracked = self.node_store.contains_key(&frame_id);
+        let new_timestamp = match access_type {
+            AccessType::Scan if is_tracked => return,
+            AccessType::Scan => self.scan_timestamp(),
+            AccessType::Unknown | AccessType::Lookup => self.current_timestamp(),
+        };
         match self.node_store.get_mut(&frame_id) {
             Some(node) => {
+                if node.is_evictable {
+                    self.evictable.remove(&node.last_accessed_timestamp);
+                    self.evictable.insert(new_timestamp, frame_id);
+                }
                 node.last_accessed_timestamp = new_timestamp;
             }
             None => {
                 let node = LruNode {
-                    frame_id,
                     is_evictable: true,
-                    last_accessed_timestamp: self.current_timestamp(),
+                    last_accessed_timestamp: new_timestamp,
                 };
 
                 self.node_store.insert(frame_id, node);
-                self.evictable_count += 1;
+                self.evictable.insert(new_timestamp, frame_id);
             }
         }
     }
@@ -94,18 +109,17 @@ impl Replacer for LruReplacer {
     /// Removes a frame from LRU entirely.
     fn remove(&mut self, frame_id: FrameId) {
         if let Some(node) = self.node_store.remove(&frame_id) {
-            // If the node was evictable, decrement the counter
             if node.is_evictable {
-                self.evictable_count -= 1;
+                self.evictable.remove(&node.last_accessed_timestamp);
             } else {
-                panic!("replacer remoev should only be called on evictable frame");
+                panic!("replacer remove should only be called on evictable frame");
             }
         }
     }
 
     /// Returns the number of evictable frames.
     fn evictable_count(&self) -> usize {
-        self.evictable_count
+        self.evictable.len()
     }
 }
 
@@ -117,9 +131,9 @@ mod tests {
     fn test_record_access() {
         let mut lru = LruReplacer::new();
 
-        lru.record_access(1);
-        lru.record_access(2);
-        lru.record_access(3);
+        lru.record_access(1, 1, AccessType::Unknown);
+        lru.record_access(2, 2, AccessType::Unknown);
+        lru.record_access(3, 3, AccessType::Unknown);
 
         assert_eq!(lru.evictable_count(), 3);
     }
@@ -128,9 +142,9 @@ mod tests {
     fn test_evict() {
         let mut lru = LruReplacer::new();
 
-        lru.record_access(1);
-        lru.record_access(2);
-        lru.record_access(3);
+        lru.record_access(1, 1, AccessType::Unknown);
+        lru.record_access(2, 2, AccessType::Unknown);
+        lru.record_access(3, 3, AccessType::Unknown);
 
         assert_eq!(lru.evictable_count(), 3);
 
@@ -144,9 +158,9 @@ mod tests {
     fn test_pin() {
         let mut lru = LruReplacer::new();
 
-        lru.record_access(1);
-        lru.record_access(2);
-        lru.record_access(3);
+        lru.record_access(1, 1, AccessType::Unknown);
+        lru.record_access(2, 2, AccessType::Unknown);
+        lru.record_access(3, 3, AccessType::Unknown);
 
         lru.pin(2);
 
@@ -160,9 +174,9 @@ mod tests {
     fn test_unpin() {
         let mut lru = LruReplacer::new();
 
-        lru.record_access(1);
-        lru.record_access(2);
-        lru.record_access(3);
+        lru.record_access(1, 1, AccessType::Unknown);
+        lru.record_access(2, 2, AccessType::Unknown);
+        lru.record_access(3, 3, AccessType::Unknown);
 
         lru.pin(2);
         assert_eq!(lru.evictable_count(), 2); // 2 is pinned, only 1 & 3 are evictable
@@ -178,9 +192,9 @@ mod tests {
     fn test_remove() {
         let mut lru = LruReplacer::new();
 
-        lru.record_access(1);
-        lru.record_access(2);
-        lru.record_access(3);
+        lru.record_access(1, 1, AccessType::Unknown);
+        lru.record_access(2, 2, AccessType::Unknown);
+        lru.record_access(3, 3, AccessType::Unknown);
 
         lru.remove(2); // Remove frame 2 directly
 
@@ -194,13 +208,13 @@ mod tests {
     fn test_record_access_multiple_times() {
         let mut lru = LruReplacer::new();
 
-        lru.record_access(1);
-        lru.record_access(2);
-        lru.record_access(3);
+        lru.record_access(1, 1, AccessType::Unknown);
+        lru.record_access(2, 2, AccessType::Unknown);
+        lru.record_access(3, 3, AccessType::Unknown);
 
         assert_eq!(lru.evictable_count(), 3);
 
-        lru.record_access(1);
+        lru.record_access(1, 1, AccessType::Unknown);
 
         assert_eq!(lru.evict(), Some(2));
         assert_eq!(lru.evict(), Some(3));
@@ -208,4 +222,60 @@ mod tests {
 
         assert_eq!(lru.evictable_count(), 0);
     }
+
+    #[test]
+    fn test_pin_then_access_keeps_recency() {
+        let mut lru = LruReplacer::new();
+
+        lru.record_access(1, 1, AccessType::Unknown);
+        lru.record_access(2, 2, AccessType::Unknown);
+        lru.record_access(3, 3, AccessType::Unknown);
+
+        // accessing a pinned frame still refreshes its recency for when it is unpinned
+        lru.pin(1);
+        lru.record_access(1, 1, AccessType::Unknown);
+        lru.unpin(1);
+
+        assert_eq!(lru.evict(), Some(2));
+        assert_eq!(lru.evict(), Some(3));
+        assert_eq!(lru.evict(), Some(1));
+    }
+
+    #[test]
+    fn test_many_frames() {
+        let mut lru = LruReplacer::new();
+
+        for frame_id in 0..1000 {
+            lru.record_access(frame_id, frame_id, AccessType::Unknown);
+        }
+        for frame_id in (0..1000).step_by(2) {
+            lru.record_access(frame_id, frame_id, AccessType::Unknown);
+        }
+
+        assert_eq!(lru.evictable_count(), 1000);
+        for frame_id in (1..1000).step_by(2).chain((0..1000).step_by(2)) {
+            assert_eq!(lru.evict(), Some(frame_id));
+        }
+        assert_eq!(lru.evict(), None);
+    }
+
+    #[test]
+    fn test_scanned_frames_are_evicted_first() {
+        let mut lru = LruReplacer::new();
+
+        lru.record_access(1, 1, AccessType::Lookup);
+        lru.record_access(2, 2, AccessType::Scan);
+        lru.record_access(3, 3, AccessType::Lookup);
+        lru.record_access(4, 4, AccessType::Scan);
+
+        // scanning a tracked frame again does not refresh it
+        lru.record_access(1, 1, AccessType::Scan);
+        // but a lookup promotes a scanned frame
+        lru.record_access(4, 4, AccessType::Lookup);
+
+        assert_eq!(lru.evict(), Some(2));
+        assert_eq!(lru.evict(), Some(1));
+        assert_eq!(lru.evict(), Some(3));
+        assert_eq!(lru.evict(), Some(4));
+    }
 }
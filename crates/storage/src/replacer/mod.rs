@@ -1,3 +1,8 @@
+pub(crate) mod arc_replacer;
+pub(crate) mod clock_pro_replacer;
+pub(crate) mod clock_replacer;
+mod ghost_list;
 pub(crate) mod lru_k_replacer;
 pub(crate) mod lru_replacer;
+#[allow(clippy::module_inception)]
 pub(crate) mod replacer;
@@ -1,6 +1,18 @@
-use crate::typedef::FrameId;
+use crate::typedef::{FrameId, PageId};
 
-pub trait Replacer {
+/// Describes why a page is accessed, so that the replacer can keep one-off accesses from
+/// displacing the working set.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AccessType {
+    #[default]
+    Unknown,
+    /// A point access, e.g. fetching a tuple by its record id.
+    Lookup,
+    /// An access of a sequential scan, which is unlikely to touch the page again soon.
+    Scan,
+}
+
+pub trait Replacer: Send {
     /// Marks a frame as unpinned, making it eligible for eviction.
     fn unpin(&mut self, frame_id: FrameId);
 
@@ -9,7 +21,9 @@ pub trait Replacer {
 
     /// Record the event that the given frame id is accessed at current timestamp.
     /// Create a new entry if frame id has not been seen before.
-    fn record_access(&mut self, frame_id: FrameId);
+    /// `page_id` is the page held by the frame, which lets adaptive policies keep track of pages
+    /// after their frame has been evicted and reused.
+    fn record_access(&mut self, frame_id: FrameId, page_id: PageId, access_type: AccessType);
 
     /// Attempts to evict a page in frame based on the replacement policy.
     /// Returns `Some(frame_id)` if a page in frame is evicted, otherwise `None`.
@@ -20,4 +34,18 @@ pub trait Replacer {
 
     /// Removes a page from the replacer. This should only be called on a page that is evictable
     fn remove(&mut self, frame_id: FrameId);
+
+    /// Puts back a frame returned by `evict` whose page stays cached after all, e.g. because it
+    /// could not be written back. This is not an access of the page, so adaptive policies
+    /// restore the frame without adapting to it.
+    fn reinsert(&mut self, frame_id: FrameId, page_id: PageId) {
+        self.record_access(frame_id, page_id, AccessType::Unknown);
+        self.unpin(frame_id);
+    }
+
+    /// Adapts the replacer to the size of the buffer pool, whose frame ids are below `capacity`.
+    /// Called when the pool is created and whenever it is resized. Frames at or above `capacity`
+    /// are removed before the pool shrinks. Replacers that do not
+    /// depend on the pool size ignore it.
+    fn set_capacity(&mut self, _capacity: usize) {}
 }